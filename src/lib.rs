@@ -1,12 +1,235 @@
+use image::{GrayImage, ImageBuffer, Pixel, RgbImage, RgbaImage};
 use img_hash::ImageHash;
 use std::path::PathBuf;
+use std::sync::Arc;
 
-/// Represents a single frame with its index, file path, and perceptual hash
+/// Represents a single frame with its index, presentation timestamp, file path,
+/// and perceptual hash
 #[derive(Clone, Debug)]
 pub struct FrameEntry {
     pub idx: usize,
+    /// Presentation timestamp in seconds, as reported by the decoder
+    pub pts: f64,
+    /// Time in seconds the frame stops being shown, when known (e.g. the end of a
+    /// frozen interval); otherwise it lasts until the next frame
+    pub end: Option<f64>,
     pub path: PathBuf,
     pub hash: ImageHash,
+    /// Optional hashes of other types, fused with `hash` by [`FrameEntry::dist`]
+    pub multi: Option<MultiHash>,
+}
+
+/// How the distances of several hash types are combined
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HashFusion {
+    /// Weighted mean of the distances
+    Weighted,
+    /// Weighted vote: the fused distance exceeds a threshold exactly when hashes
+    /// holding the majority of the weight exceed it (weighted median)
+    Vote,
+}
+
+/// Additional hashes of a frame, e.g. gradient, mean and blockhash next to the primary one
+#[derive(Clone, Debug)]
+pub struct MultiHash {
+    /// Hashes of the additional types, in the same order for every frame
+    pub extra: Vec<ImageHash>,
+    /// Weight of the primary hash followed by the weight of each extra hash
+    pub weights: Arc<[f32]>,
+    pub fusion: HashFusion,
+}
+
+impl FrameEntry {
+    /// A frame shown at `pts` seconds with a single hash
+    pub fn new(idx: usize, pts: f64, path: PathBuf, hash: ImageHash) -> Self {
+        FrameEntry { idx, pts, end: None, path, hash, multi: None }
+    }
+
+    /// Set the time the frame stops being shown
+    pub fn with_end(mut self, end: Option<f64>) -> Self {
+        self.end = end;
+        self
+    }
+
+    /// Attach hashes of other types, fused with `hash` by [`FrameEntry::dist`]
+    pub fn with_multi(mut self, multi: Option<MultiHash>) -> Self {
+        self.multi = multi;
+        self
+    }
+
+    /// Distance between two frames on the 0..=64 scale of the primary hash.
+    ///
+    /// Without extra hashes this is the Hamming distance of `hash`. Otherwise the
+    /// distance of every hash type is scaled to 64 bits and fused according to
+    /// [`HashFusion`]. Bit-level operations such as [`mean_hash`] only use `hash`.
+    pub fn dist(&self, other: &FrameEntry) -> u32 {
+        let (a, b) = match (&self.multi, &other.multi) {
+            (Some(a), Some(b)) if a.extra.len() == b.extra.len() => (a, b),
+            _ => return self.hash.dist(&other.hash),
+        };
+
+        let scaled = |x: &ImageHash, y: &ImageHash| {
+            let bits = (x.as_bytes().len() * 8).max(1) as f32;
+            x.dist(y) as f32 * 64.0 / bits
+        };
+        let mut votes: Vec<(f32, f32)> = std::iter::once(scaled(&self.hash, &other.hash))
+            .chain(a.extra.iter().zip(&b.extra).map(|(x, y)| scaled(x, y)))
+            .zip(a.weights.iter().copied())
+            .collect();
+        let total: f32 = votes.iter().map(|&(_, w)| w).sum();
+        if total <= 0.0 {
+            return self.hash.dist(&other.hash);
+        }
+
+        let fused = match a.fusion {
+            HashFusion::Weighted => votes.iter().map(|&(d, w)| d * w).sum::<f32>() / total,
+            HashFusion::Vote => {
+                votes.sort_by(|x, y| x.0.total_cmp(&y.0));
+                let mut acc = 0.0;
+                votes
+                    .iter()
+                    .find(|&&(_, w)| {
+                        acc += w;
+                        acc * 2.0 >= total
+                    })
+                    .map_or(0.0, |&(d, _)| d)
+            }
+        };
+        fused.round() as u32
+    }
+}
+
+/// A strategy for the initial grouping of consecutive frames into clusters.
+///
+/// Implementations return a partition of `0..frames.len()` into ordered,
+/// contiguous, non-empty runs of frame indices. The result is typically refined
+/// afterwards by [`merge_short_clusters`].
+pub trait ClusterStrategy {
+    fn cluster(&self, frames: &[FrameEntry], threshold: u32) -> Vec<Vec<usize>>;
+}
+
+/// Anchor strategy: each cluster is compared against its first frame.
+///
+/// Works well for hard cuts and clean screen captures, but gradual drift
+/// (slow zooms, auto-exposure) eventually splits a single slide in several pieces.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AnchorStrategy;
+
+impl ClusterStrategy for AnchorStrategy {
+    fn cluster(&self, frames: &[FrameEntry], threshold: u32) -> Vec<Vec<usize>> {
+        let mut clusters: Vec<Vec<usize>> = Vec::new();
+        if frames.is_empty() {
+            return clusters;
+        }
+        let mut cur: Vec<usize> = vec![0];
+        let mut anchor = &frames[0];
+
+        for (i, frame) in frames.iter().enumerate().skip(1) {
+            let d = frame.dist(anchor);
+            if d <= threshold {
+                cur.push(i);
+            } else {
+                clusters.push(cur);
+                cur = vec![i];
+                anchor = frame;
+            }
+        }
+        clusters.push(cur);
+        clusters
+    }
+}
+
+/// Sliding-window strategy: each frame is compared against the rolling mean
+/// (bitwise majority) of the last `window` hashes of the current cluster.
+/// Only the primary hash is used, see [`FrameEntry::dist`].
+///
+/// The reference follows slow drift, so it tolerates camera-recorded lectures
+/// and gradually changing captures that the anchor strategy over-splits.
+#[derive(Clone, Copy, Debug)]
+pub struct SlidingWindowStrategy {
+    pub window: usize,
+}
+
+impl Default for SlidingWindowStrategy {
+    fn default() -> Self {
+        SlidingWindowStrategy { window: 5 }
+    }
+}
+
+impl ClusterStrategy for SlidingWindowStrategy {
+    fn cluster(&self, frames: &[FrameEntry], threshold: u32) -> Vec<Vec<usize>> {
+        let mut clusters: Vec<Vec<usize>> = Vec::new();
+        if frames.is_empty() {
+            return clusters;
+        }
+        let window = self.window.max(1);
+        let mut cur: Vec<usize> = vec![0];
+
+        for (i, frame) in frames.iter().enumerate().skip(1) {
+            let start = cur.len().saturating_sub(window);
+            let reference = mean_hash(cur[start..].iter().map(|&j| &frames[j].hash));
+            if frame.hash.dist(&reference) <= threshold {
+                cur.push(i);
+            } else {
+                clusters.push(cur);
+                cur = vec![i];
+            }
+        }
+        clusters.push(cur);
+        clusters
+    }
+}
+
+/// Change-point strategy: optimal segmentation of the hash sequence with PELT.
+///
+/// Instead of a greedy threshold, every frame position is a candidate boundary
+/// and the partition minimizing the total within-segment hash dispersion plus
+/// `penalty` per segment is chosen (see [`segment_change_points`]). Slow
+/// cross-fades that the anchor loop splits into several pieces stay a single
+/// boundary. The `threshold` argument is ignored and only the primary hash is used.
+#[derive(Clone, Copy, Debug)]
+pub struct ChangePointStrategy {
+    pub penalty: f64,
+}
+
+impl Default for ChangePointStrategy {
+    fn default() -> Self {
+        ChangePointStrategy { penalty: 20.0 }
+    }
+}
+
+impl ClusterStrategy for ChangePointStrategy {
+    fn cluster(&self, frames: &[FrameEntry], _threshold: u32) -> Vec<Vec<usize>> {
+        segment_change_points(frames, self.penalty)
+    }
+}
+
+/// Bitwise majority of a non-empty set of equally sized hashes (ties resolve to 0).
+///
+/// This is the Hamming-space "mean": the hash minimizing the summed distance
+/// to all inputs.
+pub fn mean_hash<'a>(hashes: impl IntoIterator<Item = &'a ImageHash>) -> ImageHash {
+    let mut counts: Vec<u32> = Vec::new();
+    let mut n = 0u32;
+    for h in hashes {
+        let bytes = h.as_bytes();
+        if counts.is_empty() {
+            counts = vec![0; bytes.len() * 8];
+        }
+        for (bit, count) in counts.iter_mut().enumerate() {
+            if bytes[bit / 8] & (0x80 >> (bit % 8)) != 0 {
+                *count += 1;
+            }
+        }
+        n += 1;
+    }
+    let mut bytes = vec![0u8; counts.len() / 8];
+    for (bit, &count) in counts.iter().enumerate() {
+        if count * 2 > n {
+            bytes[bit / 8] |= 0x80 >> (bit % 8);
+        }
+    }
+    ImageHash::from_bytes(&bytes).expect("mean_hash requires at least one hash")
 }
 
 /// Initial clustering: anchor strategy
@@ -15,25 +238,7 @@ pub struct FrameEntry {
 /// an anchor frame, and subsequent frames are added to the cluster if their
 /// hash distance to the anchor is within the threshold.
 pub fn cluster_frames(frames: &[FrameEntry], threshold: u32) -> Vec<Vec<usize>> {
-    let mut clusters: Vec<Vec<usize>> = Vec::new();
-    if frames.is_empty() {
-        return clusters;
-    }
-    let mut cur: Vec<usize> = vec![0];
-    let mut anchor = &frames[0].hash;
-
-    for i in 1..frames.len() {
-        let d = frames[i].hash.dist(anchor);
-        if d <= threshold {
-            cur.push(i);
-        } else {
-            clusters.push(cur);
-            cur = vec![i];
-            anchor = &frames[i].hash;
-        }
-    }
-    clusters.push(cur);
-    clusters
+    AnchorStrategy.cluster(frames, threshold)
 }
 
 /// Merge clusters shorter than min_stable_seconds into neighbors to avoid counting transition blobs.
@@ -41,21 +246,81 @@ pub fn cluster_frames(frames: &[FrameEntry], threshold: u32) -> Vec<Vec<usize>>
 /// This function performs two types of merging:
 /// 1. Merges clusters that are too short (less than min_stable_seconds) into their neighbors
 /// 2. Merges "micro-splits" where adjacent clusters have very similar boundary frames
+///
+/// Durations come from the frame timestamps, see [`cluster_span`].
 pub fn merge_short_clusters(
     clusters: &mut Vec<Vec<usize>>,
     frames: &[FrameEntry],
     min_stable_seconds: f32,
-    fps: f32,
     threshold: u32,
 ) {
-    let min_len = (min_stable_seconds * fps).ceil() as usize;
+    merge_short_pass(clusters, frames, min_stable_seconds, frame_interval(frames));
+    merge_micro_splits(clusters, frames, threshold);
+}
+
+/// Tolerance for comparing durations derived from rounded decoder timestamps
+const DURATION_EPSILON: f64 = 1e-3;
+
+/// Typical spacing of the sampled frames: the median timestamp difference
+pub fn frame_interval(frames: &[FrameEntry]) -> f64 {
+    median_interval(frames.windows(2).map(|w| w[1].pts - w[0].pts))
+}
+
+/// Median of the positive timestamp differences, 0 if there are none
+fn median_interval(deltas: impl Iterator<Item = f64>) -> f64 {
+    let mut deltas: Vec<f64> = deltas.filter(|d| *d > 0.0).collect();
+    if deltas.is_empty() {
+        return 0.0;
+    }
+    deltas.sort_by(f64::total_cmp);
+    deltas[deltas.len() / 2]
+}
+
+/// Start and end time in seconds of a non-empty cluster.
+///
+/// A cluster lasts until the [`FrameEntry::end`] of its last frame, if known, or
+/// else until the next frame is shown; the last cluster of the video then lasts
+/// one typical frame interval past its last frame.
+pub fn cluster_span(cluster: &[usize], frames: &[FrameEntry]) -> (f64, f64) {
+    span_with_interval(cluster, frames, frame_interval(frames))
+}
+
+/// [`cluster_span`] with a precomputed interval for the final frame
+fn span_with_interval(cluster: &[usize], frames: &[FrameEntry], interval: f64) -> (f64, f64) {
+    let last = *cluster.last().unwrap();
+    let end = match (frames[last].end, frames.get(last + 1)) {
+        (Some(end), _) => end,
+        (None, Some(next)) => next.pts,
+        (None, None) => frames[last].pts + interval,
+    };
+    (frames[cluster[0]].pts, end)
+}
 
-    // First pass: merge clusters that are too short
+/// Whether a cluster stays on screen for at least `min_stable_seconds`
+fn is_stable(
+    cluster: &[usize],
+    frames: &[FrameEntry],
+    min_stable_seconds: f32,
+    interval: f64,
+) -> bool {
+    let (start, end) = span_with_interval(cluster, frames, interval);
+    end - start + DURATION_EPSILON >= f64::from(min_stable_seconds)
+}
+
+/// First pass of [`merge_short_clusters`]: merge clusters that are too short.
+///
+/// `interval` is the duration credited to the very last frame of the video.
+fn merge_short_pass(
+    clusters: &mut Vec<Vec<usize>>,
+    frames: &[FrameEntry],
+    min_stable_seconds: f32,
+    interval: f64,
+) {
     loop {
         let mut changed = false;
         let mut i = 0;
         while i < clusters.len() {
-            if clusters[i].len() < min_len {
+            if !is_stable(&clusters[i], frames, min_stable_seconds, interval) {
                 let merge_target = if clusters.len() == 1 {
                     None
                 } else if i == 0 {
@@ -64,11 +329,11 @@ pub fn merge_short_clusters(
                     Some(i - 1)
                 } else {
                     // Choose the neighbor that's most similar
-                    let cur_first = &frames[clusters[i][0]].hash;
-                    let cur_last = &frames[*clusters[i].last().unwrap()].hash;
+                    let cur_first = &frames[clusters[i][0]];
+                    let cur_last = &frames[*clusters[i].last().unwrap()];
 
-                    let prev_last = &frames[*clusters[i - 1].last().unwrap()].hash;
-                    let next_first = &frames[clusters[i + 1][0]].hash;
+                    let prev_last = &frames[*clusters[i - 1].last().unwrap()];
+                    let next_first = &frames[clusters[i + 1][0]];
 
                     let d_prev = cur_first.dist(prev_last);
                     let d_next = cur_last.dist(next_first);
@@ -85,7 +350,9 @@ pub fn merge_short_clusters(
                         clusters[t].append(&mut take);
                         i = t;
                     } else {
-                        clusters[t - 1].append(&mut take);
+                        // Prepend so the merged cluster stays in frame order
+                        take.append(&mut clusters[t - 1]);
+                        clusters[t - 1] = take;
                         i = t - 1;
                     }
                     changed = true;
@@ -98,13 +365,14 @@ pub fn merge_short_clusters(
             break;
         }
     }
+}
 
-    // Second pass: merge micro-splits (adjacent clusters with very similar boundaries)
+/// Second pass of [`merge_short_clusters`]: merge micro-splits (adjacent clusters
+/// with very similar boundaries)
+fn merge_micro_splits(clusters: &mut Vec<Vec<usize>>, frames: &[FrameEntry], threshold: u32) {
     let mut i = 0;
     while i + 1 < clusters.len() {
-        let a_last = frames[*clusters[i].last().unwrap()].hash.clone();
-        let b_first = frames[clusters[i + 1][0]].hash.clone();
-        if a_last.dist(&b_first) <= threshold / 2 {
+        if is_micro_split(&clusters[i], &clusters[i + 1], frames, threshold) {
             let mut tail = clusters.remove(i + 1);
             clusters[i].append(&mut tail);
         } else {
@@ -112,3 +380,1367 @@ pub fn merge_short_clusters(
         }
     }
 }
+
+/// Whether cluster `b`, directly following `a`, continues it across a spurious split
+fn is_micro_split(a: &[usize], b: &[usize], frames: &[FrameEntry], threshold: u32) -> bool {
+    let a_last = &frames[*a.last().unwrap()];
+    let b_first = &frames[b[0]];
+    a_last.dist(b_first) <= threshold / 2
+}
+
+/// Streaming counterpart of [`cluster_frames`] followed by [`merge_short_clusters`].
+///
+/// Frames are pushed one at a time and finalized clusters are returned as soon
+/// as no later frame can change them: a run of short anchor clusters is settled
+/// once a stable (long enough) cluster follows it, and a settled cluster is
+/// released once the first frame after it is known not to be a micro-split.
+/// The lookahead is therefore bounded by the next stable slide, and capped at
+/// [`with_max_lookahead`](Self::with_max_lookahead) frames for streams that
+/// never settle: once that many frames are held, every cluster but the open one
+/// is emitted as if the stream had ended there.
+///
+/// Unless the cap is reached, concatenating the output of every
+/// [`push`](Self::push) and of [`finish`](Self::finish) yields exactly the batch
+/// result for the same frames. Cluster entries are indices in push order. Only
+/// the frames of clusters not emitted yet are held, plus one timestamp
+/// difference per frame for the duration of the last frame.
+#[derive(Clone, Debug)]
+pub struct IncrementalClusterer {
+    threshold: u32,
+    min_stable_seconds: f32,
+    max_lookahead: usize,
+    /// Frames of the clusters not emitted yet; `frames[0]` was pushed as `base`
+    frames: Vec<FrameEntry>,
+    base: usize,
+    /// Timestamp of the latest frame and differences between consecutive frames
+    last_pts: Option<f64>,
+    deltas: Vec<f64>,
+    /// Anchor frame of the open (last pending) cluster
+    anchor: Option<usize>,
+    /// Anchor clusters not yet settled by the short-cluster pass; the first one,
+    /// if long, is carried over from the previous settlement
+    pending: Vec<Vec<usize>>,
+    /// Settled cluster that a following micro-split may still extend
+    tail: Option<Vec<usize>>,
+}
+
+/// Default for [`IncrementalClusterer::with_max_lookahead`]: an hour at 1 fps
+pub const DEFAULT_MAX_LOOKAHEAD: usize = 3600;
+
+impl IncrementalClusterer {
+    /// Same parameters as [`merge_short_clusters`]
+    pub fn new(threshold: u32, min_stable_seconds: f32) -> Self {
+        IncrementalClusterer {
+            threshold,
+            min_stable_seconds,
+            max_lookahead: DEFAULT_MAX_LOOKAHEAD,
+            frames: Vec::new(),
+            base: 0,
+            last_pts: None,
+            deltas: Vec::new(),
+            anchor: None,
+            pending: Vec::new(),
+            tail: None,
+        }
+    }
+
+    /// Hold at most `frames` frames (at least 1) before forcing clusters out
+    pub fn with_max_lookahead(mut self, frames: usize) -> Self {
+        self.max_lookahead = frames.max(1);
+        self
+    }
+
+    /// Number of frames held: those of the clusters not emitted yet
+    pub fn held(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Add the next frame and return any clusters that became final
+    pub fn push(&mut self, frame: FrameEntry) -> Vec<Vec<usize>> {
+        if let Some(last) = self.last_pts.replace(frame.pts) {
+            self.deltas.push(frame.pts - last);
+        }
+        let i = self.frames.len();
+        let starts_new = match self.anchor {
+            Some(a) => frame.dist(&self.frames[a]) > self.threshold,
+            None => true,
+        };
+        self.frames.push(frame);
+
+        if starts_new {
+            self.pending.push(vec![i]);
+            self.anchor = Some(i);
+        } else {
+            self.pending.last_mut().expect("open cluster exists after first frame").push(i);
+        }
+        let mut out = self.settle();
+        if self.held() >= self.max_lookahead {
+            out.extend(self.force());
+        }
+        self.emit(out)
+    }
+
+    /// Flush everything still pending at the end of the stream
+    pub fn finish(mut self) -> Vec<Vec<usize>> {
+        let mut rest = std::mem::take(&mut self.pending);
+        self.anchor = None;
+        let interval = median_interval(self.deltas.iter().copied());
+        merge_short_pass(&mut rest, &self.frames, self.min_stable_seconds, interval);
+
+        let mut out = Vec::new();
+        for cluster in rest {
+            self.push_settled(cluster, &mut out);
+        }
+        out.extend(self.tail.take());
+        self.emit(out)
+    }
+
+    /// Turn clusters of held frames into push indices and drop the frames that
+    /// no pending cluster refers to any more
+    fn emit(&mut self, mut out: Vec<Vec<usize>>) -> Vec<Vec<usize>> {
+        for i in out.iter_mut().flatten() {
+            *i += self.base;
+        }
+        let first = self.tail.as_ref().or(self.pending.first()).map_or(self.frames.len(), |c| c[0]);
+        if first > 0 {
+            self.frames.drain(..first);
+            self.base += first;
+            let held = self.tail.iter_mut().chain(self.pending.iter_mut()).flatten();
+            for i in held.chain(self.anchor.as_mut()) {
+                *i -= first;
+            }
+        }
+        out
+    }
+
+    /// Emit every cluster but the open one, or also the open one if it alone
+    /// fills the lookahead, as if the stream ended at the latest frame
+    fn force(&mut self) -> Vec<Vec<usize>> {
+        let open = self.pending.last().map_or(0, Vec::len);
+        let n = if open < self.max_lookahead { self.pending.len() - 1 } else { self.pending.len() };
+        let mut forced: Vec<Vec<usize>> = self.pending.drain(..n).collect();
+        if self.pending.is_empty() {
+            self.anchor = None;
+        }
+        merge_short_pass(&mut forced, &self.frames, self.min_stable_seconds, 0.0);
+
+        let mut out = Vec::new();
+        for cluster in forced {
+            self.push_settled(cluster, &mut out);
+        }
+        out.extend(self.tail.take());
+        out
+    }
+
+    /// Resolve short clusters enclosed by two long ones.
+    ///
+    /// A long cluster is never merged away and the short-cluster decision only
+    /// looks at the frames adjacent to the short run, so everything up to the
+    /// last long cluster is final except that cluster's own tail.
+    ///
+    /// Closed clusters have an exact duration; the open one is only known to
+    /// last until its latest frame, so no interval is credited to it (which
+    /// can only delay, never change, its classification as long).
+    fn settle(&mut self) -> Vec<Vec<usize>> {
+        let min = self.min_stable_seconds;
+        let longs: Vec<usize> = (0..self.pending.len())
+            .filter(|&p| is_stable(&self.pending[p], &self.frames, min, 0.0))
+            .collect();
+        if longs.len() < 2 {
+            return Vec::new();
+        }
+
+        let end = longs[longs.len() - 1];
+        let mut settled: Vec<Vec<usize>> = self.pending.drain(..=end).collect();
+        merge_short_pass(&mut settled, &self.frames, min, 0.0);
+        let carry = settled.pop().expect("settled run ends with a long cluster");
+
+        let mut out = Vec::new();
+        for cluster in settled {
+            self.push_settled(cluster, &mut out);
+        }
+        // The carried cluster's first frame is final, so the tail can be
+        // released early unless the carry will continue it.
+        if let Some(tail) = self.tail.take() {
+            if is_micro_split(&tail, &carry, &self.frames, self.threshold) {
+                self.tail = Some(tail);
+            } else {
+                out.push(tail);
+            }
+        }
+        self.pending.insert(0, carry);
+        out
+    }
+
+    /// Micro-split pass over settled clusters, in order
+    fn push_settled(&mut self, cluster: Vec<usize>, out: &mut Vec<Vec<usize>>) {
+        match self.tail.take() {
+            Some(mut tail) if is_micro_split(&tail, &cluster, &self.frames, self.threshold) => {
+                tail.extend(cluster);
+                self.tail = Some(tail);
+            }
+            Some(tail) => {
+                out.push(tail);
+                self.tail = Some(cluster);
+            }
+            None => self.tail = Some(cluster),
+        }
+    }
+}
+
+/// Group clusters that show the same slide anywhere in the timeline.
+///
+/// `merge_short_clusters` only looks at neighbors, so a slide the presenter
+/// returns to later ends up in several non-adjacent clusters. Each cluster is
+/// summarized by its [`mean_hash`] and joins the closest earlier group within
+/// `threshold`; otherwise it starts a new group.
+///
+/// Returns groups of indices into `clusters`, ordered by first appearance.
+pub fn dedupe_clusters(
+    clusters: &[Vec<usize>],
+    frames: &[FrameEntry],
+    threshold: u32,
+) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut refs: Vec<ImageHash> = Vec::new();
+
+    for (ci, cluster) in clusters.iter().enumerate() {
+        if cluster.is_empty() {
+            continue;
+        }
+        let h = mean_hash(cluster.iter().map(|&i| &frames[i].hash));
+        let best = refs
+            .iter()
+            .enumerate()
+            .map(|(gi, r)| (gi, r.dist(&h)))
+            .filter(|&(_, d)| d <= threshold)
+            .min_by_key(|&(_, d)| d);
+
+        match best {
+            Some((gi, _)) => groups[gi].push(ci),
+            None => {
+                groups.push(vec![ci]);
+                refs.push(h);
+            }
+        }
+    }
+    groups
+}
+
+/// Estimate a clustering threshold from the consecutive-frame distance series.
+///
+/// Most consecutive pairs sit on the same slide (small distances) while a few
+/// straddle a slide change (large distances). Otsu's method picks the split of
+/// the distance histogram that maximizes the between-class variance; the
+/// returned value is the largest distance still considered "same slide".
+/// Empty histogram bins between the two classes form a plateau of equally good
+/// splits, in which case the middle of the gap is chosen.
+///
+/// Returns `None` when there are fewer than two frames or every distance is
+/// identical, i.e. the distribution has nothing to split.
+pub fn estimate_threshold(frames: &[FrameEntry]) -> Option<u32> {
+    let dists: Vec<u32> = frames.windows(2).map(|w| w[0].dist(&w[1])).collect();
+    let max = *dists.iter().max()?;
+    if dists.iter().all(|&d| d == max) {
+        return None;
+    }
+
+    let mut hist = vec![0u64; max as usize + 1];
+    for &d in &dists {
+        hist[d as usize] += 1;
+    }
+    let total = dists.len() as f64;
+    let sum_all: f64 = hist.iter().enumerate().map(|(d, &n)| d as f64 * n as f64).sum();
+
+    let (mut best_lo, mut best_hi, mut best) = (0u32, 0u32, f64::MIN);
+    let (mut w0, mut sum0) = (0.0f64, 0.0f64);
+    for (t, &n) in hist.iter().enumerate().take(max as usize) {
+        w0 += n as f64;
+        sum0 += t as f64 * n as f64;
+        let w1 = total - w0;
+        if w0 == 0.0 || w1 == 0.0 {
+            continue;
+        }
+        let mean0 = sum0 / w0;
+        let mean1 = (sum_all - sum0) / w1;
+        let between = w0 * w1 * (mean0 - mean1).powi(2);
+        if between > best + 1e-9 {
+            (best_lo, best_hi, best) = (t as u32, t as u32, between);
+        } else if (between - best).abs() <= 1e-9 {
+            best_hi = t as u32;
+        }
+    }
+    Some((best_lo + best_hi) / 2)
+}
+
+/// Change-point segmentation of the frame hashes using PELT (pruned exact linear time).
+///
+/// The cost of a segment is the summed per-bit variance of its hashes, which
+/// equals the sum of pairwise Hamming distances inside the segment divided by
+/// its length: a stable slide costs roughly its noise, while a segment spanning
+/// a slide change grows with the cut distance and the number of frames on each
+/// side. Each extra segment pays `penalty` (in bits), so larger penalties yield
+/// fewer slides.
+///
+/// Returns the same kind of partition as [`cluster_frames`].
+pub fn segment_change_points(frames: &[FrameEntry], penalty: f64) -> Vec<Vec<usize>> {
+    let n = frames.len();
+    if n == 0 {
+        return Vec::new();
+    }
+    let bits = frames[0].hash.as_bytes().len() * 8;
+
+    // prefix[i][b] = number of frames in 0..i with bit b set
+    let mut prefix = vec![vec![0u32; bits]; n + 1];
+    for (i, frame) in frames.iter().enumerate() {
+        let bytes = frame.hash.as_bytes();
+        let (done, rest) = prefix.split_at_mut(i + 1);
+        for (b, count) in rest[0].iter_mut().enumerate() {
+            *count = done[i][b] + u32::from(bytes[b / 8] & (0x80 >> (b % 8)) != 0);
+        }
+    }
+    let cost = |s: usize, e: usize| -> f64 {
+        let len = (e - s) as f64;
+        prefix[e]
+            .iter()
+            .zip(&prefix[s])
+            .map(|(&a, &b)| {
+                let ones = f64::from(a - b);
+                ones - ones * ones / len
+            })
+            .sum()
+    };
+
+    // best[t] = optimal cost of frames 0..t; last[t] = start of its final segment
+    let mut best = vec![0.0f64; n + 1];
+    let mut last = vec![0usize; n + 1];
+    best[0] = -penalty;
+    let mut candidates: Vec<usize> = vec![0];
+    for t in 1..=n {
+        let (s_best, c_best) = candidates
+            .iter()
+            .map(|&s| (s, best[s] + cost(s, t) + penalty))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .expect("candidate set is never empty");
+        best[t] = c_best;
+        last[t] = s_best;
+        candidates.retain(|&s| best[s] + cost(s, t) <= best[t]);
+        candidates.push(t);
+    }
+
+    let mut clusters = Vec::new();
+    let mut end = n;
+    while end > 0 {
+        let start = last[end];
+        clusters.push((start..end).collect());
+        end = start;
+    }
+    clusters.reverse();
+    clusters
+}
+
+/// Tolerance (in gray levels) for a pixel to count as unchanged or as background
+const BUILD_PIXEL_TOLERANCE: u8 = 32;
+/// Fraction of pixels that must change for a build step (ignores compression noise)
+const BUILD_MIN_CHANGE: f32 = 0.002;
+/// Above this fraction of changed pixels the change is a new slide, not a build step
+const BUILD_MAX_CHANGE: f32 = 0.4;
+/// Fraction of the changed pixels that must land on previously blank background
+const BUILD_MIN_ADDED: f32 = 0.95;
+
+/// Whether `next` only adds content on top of `prev`, as in an animated bullet build.
+///
+/// Both images are small grayscale thumbnails of the same size. The background
+/// is the most common gray level of `prev`; `next` is a build step when a
+/// noticeable but limited part of the frame changed and nearly all of the
+/// changed pixels were background before, i.e. nothing was removed or moved.
+pub fn is_build_step(prev: &GrayImage, next: &GrayImage) -> bool {
+    if prev.dimensions() != next.dimensions() || prev.as_raw().is_empty() {
+        return false;
+    }
+
+    let mut hist = [0u32; 256];
+    for p in prev.as_raw() {
+        hist[*p as usize] += 1;
+    }
+    let background = (0..256).max_by_key(|&v| hist[v]).unwrap_or(0) as u8;
+
+    let (mut changed, mut added) = (0usize, 0usize);
+    for (&a, &b) in prev.as_raw().iter().zip(next.as_raw()) {
+        if a.abs_diff(b) > BUILD_PIXEL_TOLERANCE {
+            changed += 1;
+            if a.abs_diff(background) <= BUILD_PIXEL_TOLERANCE {
+                added += 1;
+            }
+        }
+    }
+
+    let total = prev.as_raw().len() as f32;
+    let fraction = changed as f32 / total;
+    (BUILD_MIN_CHANGE..=BUILD_MAX_CHANGE).contains(&fraction)
+        && added as f32 >= BUILD_MIN_ADDED * changed as f32
+}
+
+/// Recognize incremental slide builds in a clustering.
+///
+/// Each cluster is first split wherever a frame adds content to its
+/// predecessor (steps the micro-split rule merged away), then consecutive
+/// clusters are chained when the next one starts as a build step of the
+/// previous one. `thumbs[i]` is the thumbnail of frame `i`.
+///
+/// Returns one entry per build sequence, each listing its steps (frame index
+/// runs) in order; slides without a build are a single step.
+pub fn split_builds(clusters: &[Vec<usize>], thumbs: &[GrayImage]) -> Vec<Vec<Vec<usize>>> {
+    let mut builds: Vec<Vec<Vec<usize>>> = Vec::new();
+
+    for cluster in clusters.iter().filter(|c| !c.is_empty()) {
+        let mut steps: Vec<Vec<usize>> = vec![vec![cluster[0]]];
+        for w in cluster.windows(2) {
+            if is_build_step(&thumbs[w[0]], &thumbs[w[1]]) {
+                steps.push(vec![w[1]]);
+            } else {
+                steps.last_mut().unwrap().push(w[1]);
+            }
+        }
+
+        let continues = builds.last().is_some_and(|b: &Vec<Vec<usize>>| {
+            let prev_last = *b.last().unwrap().last().unwrap();
+            is_build_step(&thumbs[prev_last], &thumbs[cluster[0]])
+        });
+        if continues {
+            builds.last_mut().unwrap().extend(steps);
+        } else {
+            builds.push(steps);
+        }
+    }
+    builds
+}
+
+/// Boundary and consistency report for one cluster of a final clustering
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClusterDiagnostics {
+    /// Mean Hamming distance of the cluster's frames to their [`mean_hash`]
+    pub spread: f32,
+    /// Distance from the previous cluster's last frame to this cluster's first frame
+    pub boundary_prev: Option<u32>,
+    /// Distance from this cluster's last frame to the next cluster's first frame
+    pub boundary_next: Option<u32>,
+    /// Number of initial clusters this one was merged from (1 = survived unchanged)
+    pub merged_from: usize,
+    /// Overall confidence in 0.0..=1.0; low values deserve a manual check
+    pub confidence: f32,
+}
+
+/// Weight applied to the confidence of clusters produced by a merge
+const MERGED_CONFIDENCE: f32 = 0.75;
+
+/// Score how trustworthy each cluster of `clusters` is.
+///
+/// `initial` is the clustering before [`merge_short_clusters`] and tells which
+/// clusters were produced by a merge. The confidence is the product of three
+/// factors: the weakest boundary distance relative to `2 * threshold`, one minus
+/// the spread relative to `threshold`, and a penalty for merged clusters.
+pub fn diagnose_clusters(
+    initial: &[Vec<usize>],
+    clusters: &[Vec<usize>],
+    frames: &[FrameEntry],
+    threshold: u32,
+) -> Vec<ClusterDiagnostics> {
+    let mut starts = vec![false; frames.len()];
+    for cluster in initial.iter().filter(|c| !c.is_empty()) {
+        starts[cluster[0]] = true;
+    }
+    let threshold = threshold.max(1) as f32;
+    let boundary = |a: &[usize], b: &[usize]| -> Option<u32> {
+        Some(frames[*a.last()?].dist(&frames[*b.first()?]))
+    };
+
+    clusters
+        .iter()
+        .enumerate()
+        .map(|(i, cluster)| {
+            let spread = if cluster.is_empty() {
+                0.0
+            } else {
+                let mean = mean_hash(cluster.iter().map(|&f| &frames[f].hash));
+                let total: u32 = cluster.iter().map(|&f| frames[f].hash.dist(&mean)).sum();
+                total as f32 / cluster.len() as f32
+            };
+            let boundary_prev = i.checked_sub(1).and_then(|p| boundary(&clusters[p], cluster));
+            let boundary_next = clusters.get(i + 1).and_then(|n| boundary(cluster, n));
+            let merged_from = cluster.iter().filter(|&&f| starts[f]).count().max(1);
+
+            let weakest = boundary_prev.into_iter().chain(boundary_next).min();
+            let boundary_score = weakest.map_or(1.0, |d| (d as f32 / (2.0 * threshold)).min(1.0));
+            let spread_score = (1.0 - spread / threshold).clamp(0.0, 1.0);
+            let merge_score = if merged_from > 1 { MERGED_CONFIDENCE } else { 1.0 };
+
+            ClusterDiagnostics {
+                spread,
+                boundary_prev,
+                boundary_next,
+                merged_from,
+                confidence: boundary_score * spread_score * merge_score,
+            }
+        })
+        .collect()
+}
+
+/// How the frame(s) written for a cluster are chosen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RepresentativeRule {
+    /// Frames evenly spread over the cluster; a single one is the median frame
+    Median,
+    /// Frames with the highest sharpness score (e.g. [`laplacian_variance`])
+    Sharpest,
+    /// The last frames that still match the cluster, just before the next transition
+    LastStable,
+    /// Frames closest to the cluster's hash centroid
+    Centroid,
+}
+
+/// Variance of the 4-neighbour Laplacian of an image, a standard focus measure.
+///
+/// Blurred frames (half-way through a fade, motion blur, refocusing camera) have
+/// weak edges and score low. Images smaller than 3x3 score 0.
+pub fn laplacian_variance(img: &GrayImage) -> f64 {
+    let (w, h) = img.dimensions();
+    if w < 3 || h < 3 {
+        return 0.0;
+    }
+    let px = |x: u32, y: u32| f64::from(img.get_pixel(x, y)[0]);
+    let (mut sum, mut sum_sq) = (0.0, 0.0);
+    for y in 1..h - 1 {
+        for x in 1..w - 1 {
+            let lap = px(x - 1, y) + px(x + 1, y) + px(x, y - 1) + px(x, y + 1) - 4.0 * px(x, y);
+            sum += lap;
+            sum_sq += lap * lap;
+        }
+    }
+    let n = f64::from((w - 2) * (h - 2));
+    let mean = sum / n;
+    sum_sq / n - mean * mean
+}
+
+/// A frame carrying the [`mean_hash`] of every hash type of a non-empty cluster
+fn centroid(cluster: &[usize], frames: &[FrameEntry]) -> FrameEntry {
+    let first = &frames[cluster[0]];
+    let multi = first.multi.as_ref().map(|m| MultiHash {
+        extra: (0..m.extra.len())
+            .map(|k| {
+                mean_hash(cluster.iter().filter_map(|&f| frames[f].multi.as_ref()?.extra.get(k)))
+            })
+            .collect(),
+        ..m.clone()
+    });
+    FrameEntry {
+        hash: mean_hash(cluster.iter().map(|&f| &frames[f].hash)),
+        multi,
+        ..first.clone()
+    }
+}
+
+/// Pick up to `count` representative frames of a cluster, returned in time order.
+///
+/// `sharpness[i]` scores `cluster[i]` and is only used by
+/// [`RepresentativeRule::Sharpest`] (falling back to the median rule if it does
+/// not cover the cluster). [`RepresentativeRule::LastStable`] keeps the frames
+/// within `threshold / 2` of the cluster centroid, so that a fade into the next
+/// slide already underway is skipped.
+pub fn select_representatives(
+    cluster: &[usize],
+    frames: &[FrameEntry],
+    rule: RepresentativeRule,
+    count: usize,
+    threshold: u32,
+    sharpness: &[f64],
+) -> Vec<usize> {
+    let count = count.min(cluster.len());
+    if count == 0 {
+        return Vec::new();
+    }
+    let median = || -> Vec<usize> {
+        (0..count)
+            .map(|k| cluster[(2 * k + 1) * cluster.len() / (2 * count)])
+            .collect()
+    };
+
+    let mut picked: Vec<usize> = match rule {
+        RepresentativeRule::Median => return median(),
+        RepresentativeRule::Sharpest if sharpness.len() != cluster.len() => return median(),
+        RepresentativeRule::Sharpest => {
+            let mut order: Vec<usize> = (0..cluster.len()).collect();
+            order.sort_by(|&a, &b| sharpness[b].total_cmp(&sharpness[a]));
+            order.into_iter().take(count).map(|i| cluster[i]).collect()
+        }
+        RepresentativeRule::LastStable => {
+            let center = centroid(cluster, frames);
+            let stable: Vec<usize> = cluster
+                .iter()
+                .copied()
+                .filter(|&f| frames[f].dist(&center) <= threshold / 2)
+                .collect();
+            let pool = if stable.is_empty() { cluster } else { &stable };
+            pool[pool.len().saturating_sub(count)..].to_vec()
+        }
+        RepresentativeRule::Centroid => {
+            let center = centroid(cluster, frames);
+            let middle = cluster.len() / 2;
+            let mut order: Vec<usize> = (0..cluster.len()).collect();
+            order.sort_by_key(|&i| (frames[cluster[i]].dist(&center), i.abs_diff(middle)));
+            order.into_iter().take(count).map(|i| cluster[i]).collect()
+        }
+    };
+    picked.sort_unstable();
+    picked
+}
+
+/// Pixel-wise combination of the frames of a cluster into one clean image
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameFusion {
+    /// Per-channel median: removes transient overlays such as cursors or laser dots
+    Median,
+    /// Per-channel mean after dropping the darkest and brightest [`FUSION_TRIM`]
+    /// of the values: also averages out compression and sensor noise
+    TrimmedMean,
+}
+
+/// Fraction of values dropped at each end by [`FrameFusion::TrimmedMean`]
+pub const FUSION_TRIM: f32 = 0.25;
+
+/// Fuse equally sized frames into a single image.
+///
+/// Returns `None` if `images` is empty or the sizes differ.
+pub fn fuse_frames(images: &[RgbImage], mode: FrameFusion) -> Option<RgbImage> {
+    let (w, h) = images.first()?.dimensions();
+    if images.iter().any(|img| img.dimensions() != (w, h)) {
+        return None;
+    }
+
+    let n = images.len();
+    let trim = match mode {
+        FrameFusion::Median => 0,
+        FrameFusion::TrimmedMean => ((n as f32 * FUSION_TRIM) as usize).min((n - 1) / 2),
+    };
+    let mut values = vec![0u8; n];
+    let raw: Vec<u8> = (0..images[0].as_raw().len())
+        .map(|i| {
+            for (v, img) in values.iter_mut().zip(images) {
+                *v = img.as_raw()[i];
+            }
+            values.sort_unstable();
+            match mode {
+                FrameFusion::Median if n % 2 == 1 => values[n / 2],
+                FrameFusion::Median => {
+                    (u16::from(values[n / 2 - 1]) + u16::from(values[n / 2])).div_ceil(2) as u8
+                }
+                FrameFusion::TrimmedMean => {
+                    let kept = &values[trim..n - trim];
+                    let sum: u32 = kept.iter().map(|&v| u32::from(v)).sum();
+                    ((sum as f32 / kept.len() as f32).round()) as u8
+                }
+            }
+        })
+        .collect();
+    RgbImage::from_raw(w, h, raw)
+}
+
+/// One coordinate of a [`Rect`]: absolute pixels or a fraction of the frame size
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Coord {
+    Pixels(u32),
+    Fraction(f32),
+}
+
+impl Coord {
+    /// Resolve to pixels along an axis of length `size`
+    pub fn to_pixels(self, size: u32) -> u32 {
+        match self {
+            Coord::Pixels(p) => p.min(size),
+            Coord::Fraction(f) => ((f.clamp(0.0, 1.0) * size as f32).round() as u32).min(size),
+        }
+    }
+
+    /// The same coordinate on a frame resized by `factor` (fractions are unchanged)
+    pub fn scaled(self, factor: f32) -> Coord {
+        match self {
+            Coord::Pixels(p) => Coord::Pixels((p as f32 * factor).round() as u32),
+            fraction => fraction,
+        }
+    }
+}
+
+/// Parses an integer as pixels and a decimal (`0.25`) as a fraction of the frame
+impl std::str::FromStr for Coord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let v = s.trim();
+        if v.contains('.') {
+            match v.parse::<f32>() {
+                Ok(f) if (0.0..=1.0).contains(&f) => Ok(Coord::Fraction(f)),
+                _ => Err(format!("'{v}' is not a fraction in 0.0..=1.0")),
+            }
+        } else {
+            v.parse::<u32>()
+                .map(Coord::Pixels)
+                .map_err(|_| format!("'{v}' is not a pixel count"))
+        }
+    }
+}
+
+/// Formats as accepted by the parser: fractions always carry a decimal point
+impl std::fmt::Display for Coord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Coord::Pixels(p) => write!(f, "{p}"),
+            // Display never uses exponents and prints the shortest round-tripping digits
+            Coord::Fraction(v) if v.fract() == 0.0 => write!(f, "{v}.0"),
+            Coord::Fraction(v) => write!(f, "{v}"),
+        }
+    }
+}
+
+/// A rectangle of the frame: left, top, width, height
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    pub x: Coord,
+    pub y: Coord,
+    pub w: Coord,
+    pub h: Coord,
+}
+
+impl Rect {
+    /// Pixel bounds `(x, y, w, h)` within a `width` x `height` frame, clipped to the frame
+    pub fn to_pixels(&self, width: u32, height: u32) -> (u32, u32, u32, u32) {
+        let x = self.x.to_pixels(width);
+        let y = self.y.to_pixels(height);
+        let w = self.w.to_pixels(width).min(width - x);
+        let h = self.h.to_pixels(height).min(height - y);
+        (x, y, w, h)
+    }
+
+    /// The same rectangle on a frame resized by `sx` horizontally and `sy` vertically
+    pub fn scaled(&self, sx: f32, sy: f32) -> Rect {
+        let (x, y) = (self.x.scaled(sx), self.y.scaled(sy));
+        Rect { x, y, w: self.w.scaled(sx), h: self.h.scaled(sy) }
+    }
+}
+
+/// Parses `X,Y,W,H`, each a [`Coord`]
+impl std::str::FromStr for Rect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(',').collect();
+        let [x, y, w, h] = parts[..] else {
+            return Err(format!("expected X,Y,W,H, got '{s}'"));
+        };
+        Ok(Rect { x: x.parse()?, y: y.parse()?, w: w.parse()?, h: h.parse()? })
+    }
+}
+
+impl std::fmt::Display for Rect {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{},{},{}", self.x, self.y, self.w, self.h)
+    }
+}
+
+/// Regions of the frame that take part in hashing.
+///
+/// With include rectangles only their union is kept; exclude rectangles are
+/// removed on top of that. Masked pixels are painted black in every frame, so
+/// they never contribute to hash distances.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RegionMask {
+    pub include: Vec<Rect>,
+    pub exclude: Vec<Rect>,
+}
+
+impl RegionMask {
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// The same mask on a frame resized by `sx` horizontally and `sy` vertically
+    pub fn scaled(&self, sx: f32, sy: f32) -> RegionMask {
+        let scale = |rects: &[Rect]| rects.iter().map(|r| r.scaled(sx, sy)).collect();
+        RegionMask { include: scale(&self.include), exclude: scale(&self.exclude) }
+    }
+
+    /// Paint the masked-out pixels of `img` black
+    pub fn apply(&self, img: &mut RgbaImage) {
+        if self.is_empty() {
+            return;
+        }
+        let (width, height) = img.dimensions();
+        let pixels = |rects: &[Rect]| -> Vec<(u32, u32, u32, u32)> {
+            rects.iter().map(|r| r.to_pixels(width, height)).collect()
+        };
+        let (include, exclude) = (pixels(&self.include), pixels(&self.exclude));
+        let inside = |x: u32, y: u32, &(rx, ry, rw, rh): &(u32, u32, u32, u32)| {
+            (rx..rx + rw).contains(&x) && (ry..ry + rh).contains(&y)
+        };
+        for (x, y, p) in img.enumerate_pixels_mut() {
+            let kept = (include.is_empty() || include.iter().any(|r| inside(x, y, r)))
+                && !exclude.iter().any(|r| inside(x, y, r));
+            if !kept {
+                *p = image::Rgba([0, 0, 0, 255]);
+            }
+        }
+    }
+
+    /// Bounding box `(x, y, w, h)` of the include rectangles, if any
+    pub fn include_bounds(&self, width: u32, height: u32) -> Option<(u32, u32, u32, u32)> {
+        let rects: Vec<_> = self.include.iter().map(|r| r.to_pixels(width, height)).collect();
+        let x0 = rects.iter().map(|r| r.0).min()?;
+        let y0 = rects.iter().map(|r| r.1).min()?;
+        let x1 = rects.iter().map(|r| r.0 + r.2).max()?;
+        let y1 = rects.iter().map(|r| r.1 + r.3).max()?;
+        Some((x0, y0, x1 - x0, y1 - y0))
+    }
+}
+
+/// Side in thumbnail pixels of the grid cells used by [`detect_dynamic_regions`]
+const DYNAMIC_CELL: u32 = 8;
+/// Mean absolute difference (gray levels) above which a cell counts as changed
+const DYNAMIC_CELL_CHANGE: f32 = 6.0;
+/// Frame pairs changing more than this fraction of cells are slide transitions
+const DYNAMIC_TRANSITION: f32 = 0.5;
+/// Fraction of non-transition frame pairs in which a cell must change to be dynamic
+const DYNAMIC_MIN_FREQUENCY: f32 = 0.25;
+/// If more of the frame than this is dynamic, nothing is stable enough to mask
+const DYNAMIC_MAX_AREA: f32 = 0.5;
+
+/// Find regions that keep changing while the rest of the frame is stable.
+///
+/// `thumbs` are equally sized grayscale thumbnails of consecutive frames. They
+/// are divided in a grid of cells and the change frequency of each cell is
+/// counted over pairs of consecutive frames, ignoring slide transitions (pairs
+/// where most of the frame changes). Cells that change often are grouped in
+/// connected regions, returned as bounding rectangles in fractions of the
+/// frame, ready to be excluded from hashing. Typical finds are webcam tiles,
+/// tickers and clocks.
+pub fn detect_dynamic_regions(thumbs: &[GrayImage]) -> Vec<Rect> {
+    let Some(first) = thumbs.first() else {
+        return Vec::new();
+    };
+    let (width, height) = first.dimensions();
+    if thumbs.iter().any(|t| t.dimensions() != (width, height)) {
+        return Vec::new();
+    }
+    let cols = width.div_ceil(DYNAMIC_CELL) as usize;
+    let rows = height.div_ceil(DYNAMIC_CELL) as usize;
+    if cols == 0 || rows == 0 {
+        return Vec::new();
+    }
+
+    let mut counts = vec![0u32; cols * rows];
+    let mut pairs = 0u32;
+    for w in thumbs.windows(2) {
+        let mut diff = vec![(0u32, 0u32); cols * rows];
+        for (x, y, p) in w[0].enumerate_pixels() {
+            let cell = (y / DYNAMIC_CELL) as usize * cols + (x / DYNAMIC_CELL) as usize;
+            diff[cell].0 += u32::from(p[0].abs_diff(w[1].get_pixel(x, y)[0]));
+            diff[cell].1 += 1;
+        }
+        let changed: Vec<bool> = diff
+            .iter()
+            .map(|&(sum, n)| sum as f32 > DYNAMIC_CELL_CHANGE * n as f32)
+            .collect();
+        let fraction = changed.iter().filter(|&&c| c).count() as f32 / changed.len() as f32;
+        if fraction > DYNAMIC_TRANSITION {
+            continue;
+        }
+        pairs += 1;
+        for (count, _) in counts.iter_mut().zip(&changed).filter(|(_, &c)| c) {
+            *count += 1;
+        }
+    }
+    if pairs == 0 {
+        return Vec::new();
+    }
+
+    let mut dynamic: Vec<bool> = counts
+        .iter()
+        .map(|&c| c as f32 >= DYNAMIC_MIN_FREQUENCY * pairs as f32)
+        .collect();
+    if dynamic.iter().filter(|&&d| d).count() as f32 > DYNAMIC_MAX_AREA * dynamic.len() as f32 {
+        return Vec::new();
+    }
+
+    // Bounding boxes of 4-connected groups of dynamic cells, in cell units
+    let mut regions = Vec::new();
+    for start in 0..dynamic.len() {
+        if !dynamic[start] {
+            continue;
+        }
+        dynamic[start] = false;
+        let mut stack = vec![start];
+        let (mut c0, mut r0, mut c1, mut r1) = (cols, rows, 0, 0);
+        while let Some(cell) = stack.pop() {
+            let (c, r) = (cell % cols, cell / cols);
+            (c0, r0, c1, r1) = (c0.min(c), r0.min(r), c1.max(c + 1), r1.max(r + 1));
+            let neighbours = [
+                (c > 0).then(|| cell - 1),
+                (c + 1 < cols).then(|| cell + 1),
+                (r > 0).then(|| cell - cols),
+                (r + 1 < rows).then(|| cell + cols),
+            ];
+            for n in neighbours.into_iter().flatten() {
+                if dynamic[n] {
+                    dynamic[n] = false;
+                    stack.push(n);
+                }
+            }
+        }
+        regions.push((c0, r0, c1, r1));
+    }
+
+    let frac =
+        |cells: usize, size: u32| (cells as f32 * DYNAMIC_CELL as f32 / size as f32).min(1.0);
+    regions
+        .into_iter()
+        .map(|(c0, r0, c1, r1)| Rect {
+            x: Coord::Fraction(frac(c0, width)),
+            y: Coord::Fraction(frac(r0, height)),
+            w: Coord::Fraction(frac(c1, width) - frac(c0, width)),
+            h: Coord::Fraction(frac(r1, height) - frac(r0, height)),
+        })
+        .collect()
+}
+
+/// Rows and columns whose mean gray level never exceeds this are letterbox bars
+const BAR_MAX_LEVEL: f32 = 24.0;
+
+/// Find the content area inside letterbox or pillarbox bars.
+///
+/// `thumbs` are equally sized grayscale thumbnails sampled over the video. A
+/// border row or column belongs to a bar when its mean level stays dark in
+/// every thumbnail. Returns the content rectangle in fractions of the frame,
+/// or `None` when there are no bars (or the frames are entirely dark).
+pub fn detect_content_area(thumbs: &[GrayImage]) -> Option<Rect> {
+    let (width, height) = thumbs.first()?.dimensions();
+    if width == 0 || height == 0 || thumbs.iter().any(|t| t.dimensions() != (width, height)) {
+        return None;
+    }
+
+    let mut row_max = vec![0f32; height as usize];
+    let mut col_max = vec![0f32; width as usize];
+    for thumb in thumbs {
+        let mut rows = vec![0u32; height as usize];
+        let mut cols = vec![0u32; width as usize];
+        for (x, y, p) in thumb.enumerate_pixels() {
+            rows[y as usize] += u32::from(p[0]);
+            cols[x as usize] += u32::from(p[0]);
+        }
+        for (m, sum) in row_max.iter_mut().zip(rows) {
+            *m = m.max(sum as f32 / width as f32);
+        }
+        for (m, sum) in col_max.iter_mut().zip(cols) {
+            *m = m.max(sum as f32 / height as f32);
+        }
+    }
+
+    let content = |levels: &[f32]| -> Option<(usize, usize)> {
+        let start = levels.iter().position(|&l| l > BAR_MAX_LEVEL)?;
+        let end = levels.iter().rposition(|&l| l > BAR_MAX_LEVEL)? + 1;
+        Some((start, end))
+    };
+    let (y0, y1) = content(&row_max)?;
+    let (x0, x1) = content(&col_max)?;
+    if (x0, y0, x1, y1) == (0, 0, width as usize, height as usize) {
+        return None;
+    }
+
+    let frac = |v: usize, size: u32| v as f32 / size as f32;
+    Some(Rect {
+        x: Coord::Fraction(frac(x0, width)),
+        y: Coord::Fraction(frac(y0, height)),
+        w: Coord::Fraction(frac(x1 - x0, width)),
+        h: Coord::Fraction(frac(y1 - y0, height)),
+    })
+}
+
+/// A quadrilateral in image pixels, corners in order top-left, top-right,
+/// bottom-right, bottom-left
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quad {
+    pub corners: [(f32, f32); 4],
+}
+
+/// Four corner points given as `X1,Y1,X2,Y2,X3,Y3,X4,Y4` (top-left, top-right,
+/// bottom-right, bottom-left), each in pixels or as a fraction of the frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Corners(pub [(Coord, Coord); 4]);
+
+impl Corners {
+    /// Resolve to a quadrilateral in a `width` x `height` frame
+    pub fn to_quad(&self, width: u32, height: u32) -> Quad {
+        Quad {
+            corners: self
+                .0
+                .map(|(x, y)| (x.to_pixels(width) as f32, y.to_pixels(height) as f32)),
+        }
+    }
+}
+
+impl std::str::FromStr for Corners {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(',').collect();
+        if parts.len() != 8 {
+            return Err(format!("expected X1,Y1,X2,Y2,X3,Y3,X4,Y4, got '{s}'"));
+        }
+        let mut points = [(Coord::Pixels(0), Coord::Pixels(0)); 4];
+        for (point, pair) in points.iter_mut().zip(parts.chunks(2)) {
+            *point = (pair[0].parse()?, pair[1].parse()?);
+        }
+        Ok(Corners(points))
+    }
+}
+
+impl Quad {
+    /// Size of the rectified image: the longer of each pair of opposite edges
+    pub fn size(&self) -> (u32, u32) {
+        let len = |a: (f32, f32), b: (f32, f32)| (a.0 - b.0).hypot(a.1 - b.1);
+        let [tl, tr, br, bl] = self.corners;
+        let width = len(tl, tr).max(len(bl, br)).round().max(1.0);
+        let height = len(tl, bl).max(len(tr, br)).round().max(1.0);
+        (width as u32, height as u32)
+    }
+
+    /// The same quadrilateral in an image scaled by `sx` horizontally and `sy` vertically
+    pub fn scaled(&self, sx: f32, sy: f32) -> Quad {
+        Quad { corners: self.corners.map(|(x, y)| (x * sx, y * sy)) }
+    }
+
+    /// Homography mapping the `width` x `height` rectangle onto this quadrilateral
+    fn homography(&self, width: u32, height: u32) -> Option<[f64; 9]> {
+        let (w, h) = (f64::from(width), f64::from(height));
+        let rect = [(0.0, 0.0), (w, 0.0), (w, h), (0.0, h)];
+
+        // Solve A * [h0..h7] = b with h8 = 1 (Gaussian elimination, partial pivoting)
+        let mut a = [[0f64; 9]; 8];
+        for (k, (&(x, y), &(u, v))) in rect.iter().zip(&self.corners).enumerate() {
+            let (u, v) = (f64::from(u), f64::from(v));
+            a[2 * k] = [x, y, 1.0, 0.0, 0.0, 0.0, -u * x, -u * y, u];
+            a[2 * k + 1] = [0.0, 0.0, 0.0, x, y, 1.0, -v * x, -v * y, v];
+        }
+        for col in 0..8 {
+            let pivot = (col..8).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+            if a[pivot][col].abs() < 1e-9 {
+                return None;
+            }
+            a.swap(col, pivot);
+            let pivot_row = a[col];
+            for (_, row) in a.iter_mut().enumerate().filter(|(r, _)| *r != col) {
+                let factor = row[col] / pivot_row[col];
+                for (value, p) in row.iter_mut().zip(pivot_row).skip(col) {
+                    *value -= factor * p;
+                }
+            }
+        }
+        let mut m = [1f64; 9];
+        for (i, row) in a.iter().enumerate() {
+            m[i] = row[8] / row[i];
+        }
+        Some(m)
+    }
+
+    /// Warp the quadrilateral of `img` to a front-on image of [`Quad::size`].
+    ///
+    /// Pixels are sampled bilinearly; points outside `img` are black. Returns
+    /// `None` for a degenerate quadrilateral (e.g. three collinear corners).
+    pub fn rectify<P>(&self, img: &ImageBuffer<P, Vec<u8>>) -> Option<ImageBuffer<P, Vec<u8>>>
+    where
+        P: Pixel<Subpixel = u8>,
+    {
+        let (width, height) = self.size();
+        let m = self.homography(width, height)?;
+        let (src_w, src_h) = img.dimensions();
+        let channels = usize::from(P::CHANNEL_COUNT);
+        let raw = img.as_raw();
+        let sample = |x: i64, y: i64, c: usize| -> f64 {
+            if x < 0 || y < 0 || x >= i64::from(src_w) || y >= i64::from(src_h) {
+                return 0.0;
+            }
+            f64::from(raw[(y as usize * src_w as usize + x as usize) * channels + c])
+        };
+
+        let mut out = ImageBuffer::<P, Vec<u8>>::new(width, height);
+        for (x, y, p) in out.enumerate_pixels_mut() {
+            let (fx, fy) = (f64::from(x) + 0.5, f64::from(y) + 0.5);
+            let z = m[6] * fx + m[7] * fy + m[8];
+            let u = (m[0] * fx + m[1] * fy + m[2]) / z - 0.5;
+            let v = (m[3] * fx + m[4] * fy + m[5]) / z - 0.5;
+            let (x0, y0) = (u.floor(), v.floor());
+            let (dx, dy) = (u - x0, v - y0);
+            let (x0, y0) = (x0 as i64, y0 as i64);
+            for (c, value) in p.channels_mut().iter_mut().enumerate() {
+                let top = sample(x0, y0, c) * (1.0 - dx) + sample(x0 + 1, y0, c) * dx;
+                let bottom = sample(x0, y0 + 1, c) * (1.0 - dx) + sample(x0 + 1, y0 + 1, c) * dx;
+                *value = (top * (1.0 - dy) + bottom * dy).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+        Some(out)
+    }
+}
+
+/// The screen must cover at least this fraction of the frame to be detected
+const SCREEN_MIN_AREA: f32 = 0.1;
+/// Above this fraction the frame is already front-on (e.g. a screen capture)
+const SCREEN_MAX_AREA: f32 = 0.9;
+/// Minimum gap in gray levels between the screen and its surroundings
+const SCREEN_MIN_CONTRAST: f32 = 40.0;
+
+/// Find the bright projector screen in camera-recorded frames.
+///
+/// `thumbs` are equally sized grayscale thumbnails sampled over the video. Their
+/// average is split into bright and dark pixels (Otsu's method) and the largest
+/// connected bright region is taken as the screen; its corners are the extreme
+/// points along both diagonals. Returns the quadrilateral in thumbnail pixels,
+/// or `None` without a clear screen that is smaller than the frame.
+pub fn detect_screen_quad(thumbs: &[GrayImage]) -> Option<Quad> {
+    let (width, height) = thumbs.first()?.dimensions();
+    if thumbs.iter().any(|t| t.dimensions() != (width, height)) {
+        return None;
+    }
+    let (w, h) = (width as usize, height as usize);
+    let mut mean = vec![0f32; w * h];
+    for thumb in thumbs {
+        for (m, &p) in mean.iter_mut().zip(thumb.as_raw()) {
+            *m += f32::from(p) / thumbs.len() as f32;
+        }
+    }
+
+    // Otsu threshold on the average image
+    let mut hist = [0u32; 256];
+    for &m in &mean {
+        hist[m.round() as usize] += 1;
+    }
+    let total = mean.len() as f32;
+    let sum_all: f32 = hist.iter().enumerate().map(|(v, &c)| v as f32 * c as f32).sum();
+    let (mut best, mut level, mut gap) = (0.0, 0usize, 0.0);
+    let (mut count, mut sum) = (0.0, 0.0);
+    for (v, &c) in hist.iter().enumerate() {
+        count += c as f32;
+        sum += v as f32 * c as f32;
+        if count == 0.0 || count == total {
+            continue;
+        }
+        let (dark, bright) = (sum / count, (sum_all - sum) / (total - count));
+        let between = count * (total - count) * (bright - dark).powi(2);
+        if between > best {
+            (best, level, gap) = (between, v, bright - dark);
+        }
+    }
+    if gap < SCREEN_MIN_CONTRAST {
+        return None;
+    }
+
+    // Largest 4-connected bright region
+    let mut bright: Vec<bool> = mean.iter().map(|&m| m.round() as usize > level).collect();
+    let mut largest: Vec<usize> = Vec::new();
+    for start in 0..bright.len() {
+        if !bright[start] {
+            continue;
+        }
+        bright[start] = false;
+        let (mut stack, mut region) = (vec![start], Vec::new());
+        while let Some(i) = stack.pop() {
+            region.push(i);
+            let (x, y) = (i % w, i / w);
+            let neighbours = [
+                (x > 0).then(|| i - 1),
+                (x + 1 < w).then(|| i + 1),
+                (y > 0).then(|| i - w),
+                (y + 1 < h).then(|| i + w),
+            ];
+            for n in neighbours.into_iter().flatten() {
+                if bright[n] {
+                    bright[n] = false;
+                    stack.push(n);
+                }
+            }
+        }
+        if region.len() > largest.len() {
+            largest = region;
+        }
+    }
+    let area = largest.len() as f32 / total;
+    if !(SCREEN_MIN_AREA..=SCREEN_MAX_AREA).contains(&area) {
+        return None;
+    }
+
+    let points = largest.iter().map(|&i| ((i % w) as f32, (i / w) as f32));
+    let extreme = |key: fn(f32, f32) -> f32, corner: (f32, f32)| {
+        points
+            .clone()
+            .max_by(|a, b| key(a.0, a.1).total_cmp(&key(b.0, b.1)))
+            .map(|(x, y)| (x + corner.0, y + corner.1))
+    };
+    Some(Quad {
+        corners: [
+            extreme(|x, y| -x - y, (0.0, 0.0))?,
+            extreme(|x, y| x - y, (1.0, 0.0))?,
+            extreme(|x, y| x + y, (1.0, 1.0))?,
+            extreme(|x, y| y - x, (0.0, 1.0))?,
+        ],
+    })
+}
+
+/// Parameters chosen by [`fit_slide_count`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SlideCountFit {
+    pub threshold: u32,
+    pub min_stable_seconds: f32,
+    /// Number of clusters these parameters produce (the target, if reachable)
+    pub slides: usize,
+}
+
+/// Search thresholds `0..=64` and the given minimum stable durations for a
+/// clustering (`strategy` then [`merge_short_clusters`]) with `target` clusters.
+///
+/// Among the parameters reaching the target, the middle of the longest run of
+/// consecutive thresholds is chosen, as the most robust setting; ties go to the
+/// earlier entry of `min_stable_candidates`. If the target cannot be reached the
+/// closest count is returned. Returns `None` without frames or candidates.
+pub fn fit_slide_count(
+    frames: &[FrameEntry],
+    strategy: &dyn ClusterStrategy,
+    target: usize,
+    min_stable_candidates: &[f32],
+) -> Option<SlideCountFit> {
+    if frames.is_empty() {
+        return None;
+    }
+
+    let mut best_run: Option<(usize, SlideCountFit)> = None;
+    let mut closest: Option<SlideCountFit> = None;
+    for &min_stable_seconds in min_stable_candidates {
+        let mut run: Vec<u32> = Vec::new();
+        for threshold in 0..=64 {
+            let mut clusters = strategy.cluster(frames, threshold);
+            merge_short_clusters(&mut clusters, frames, min_stable_seconds, threshold);
+            let fit = SlideCountFit { threshold, min_stable_seconds, slides: clusters.len() };
+
+            if closest.is_none_or(|c| fit.slides.abs_diff(target) < c.slides.abs_diff(target)) {
+                closest = Some(fit);
+            }
+            if fit.slides == target {
+                run.push(threshold);
+            }
+            if fit.slides != target || threshold == 64 {
+                if !run.is_empty() && best_run.is_none_or(|(len, _)| run.len() > len) {
+                    let threshold = run[run.len() / 2];
+                    let fit = SlideCountFit { threshold, min_stable_seconds, slides: target };
+                    best_run = Some((run.len(), fit));
+                }
+                run.clear();
+            }
+        }
+    }
+    best_run.map(|(_, fit)| fit).or(closest)
+}
+
+/// Agreement of detected slide boundaries with ground truth, see [`score_boundaries`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoundaryScore {
+    /// Detected boundaries matched to a true boundary
+    pub matched: usize,
+    pub predicted: usize,
+    pub truth: usize,
+    /// Fraction of detected boundaries that are real (1.0 without detections)
+    pub precision: f64,
+    /// Fraction of real boundaries that were detected (1.0 without real ones)
+    pub recall: f64,
+    pub f1: f64,
+    /// Detected minus true number of slides
+    pub count_error: i64,
+}
+
+/// Score detected slide change times against true ones (both in seconds).
+///
+/// A detected boundary matches a true one at most `tolerance` seconds away;
+/// every boundary is matched at most once, closest pairs first.
+pub fn score_boundaries(predicted: &[f64], truth: &[f64], tolerance: f64) -> BoundaryScore {
+    let mut pairs: Vec<(f64, usize, usize)> = Vec::new();
+    for (i, p) in predicted.iter().enumerate() {
+        for (j, t) in truth.iter().enumerate() {
+            let gap = (p - t).abs();
+            if gap <= tolerance {
+                pairs.push((gap, i, j));
+            }
+        }
+    }
+    pairs.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut used_p = vec![false; predicted.len()];
+    let mut used_t = vec![false; truth.len()];
+    let mut matched = 0;
+    for (_, i, j) in pairs {
+        if !used_p[i] && !used_t[j] {
+            used_p[i] = true;
+            used_t[j] = true;
+            matched += 1;
+        }
+    }
+
+    let ratio = |n: usize, d: usize| if d == 0 { 1.0 } else { n as f64 / d as f64 };
+    let precision = ratio(matched, predicted.len());
+    let recall = ratio(matched, truth.len());
+    let f1 = if precision + recall > 0.0 {
+        2.0 * precision * recall / (precision + recall)
+    } else {
+        0.0
+    };
+    BoundaryScore {
+        matched,
+        predicted: predicted.len(),
+        truth: truth.len(),
+        precision,
+        recall,
+        f1,
+        count_error: predicted.len() as i64 - truth.len() as i64,
+    }
+}
+
+/// Parse slide change times from the text of a truth file.
+///
+/// The first comma-separated field of each line is a time accepted by
+/// [`parse_timestamp`]. Empty lines, `#` comments and a header on the first line
+/// are skipped, as are times of 0 (the start of the first slide). Returns the
+/// sorted times, or an error naming the first invalid line.
+pub fn parse_truth(text: &str) -> Result<Vec<f64>, String> {
+    let mut times = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let field = line.split(',').next().unwrap_or("").trim();
+        match parse_timestamp(field) {
+            Some(t) if t > 0.0 => times.push(t),
+            Some(_) => {}
+            None if n == 0 => {}
+            None => return Err(format!("invalid time on line {}: {}", n + 1, line)),
+        }
+    }
+    times.sort_by(f64::total_cmp);
+    Ok(times)
+}
+
+/// Parse `SS(.s)`, `MM:SS(.s)` or `HH:MM:SS(.s)` into seconds
+pub fn parse_timestamp(s: &str) -> Option<f64> {
+    let mut seconds = 0.0;
+    for (i, part) in s.split(':').enumerate() {
+        let v: f64 = part.trim().parse().ok()?;
+        if i > 2 || !v.is_finite() || v < 0.0 {
+            return None;
+        }
+        seconds = seconds * 60.0 + v;
+    }
+    Some(seconds)
+}
@@ -1,19 +1,30 @@
 use anyhow::{anyhow, Context, Result};
-use clap::{ArgAction, Parser, ValueEnum, ValueHint};
-use img_hash::HasherConfig;
+use clap::{ArgAction, Parser, Subcommand, ValueEnum, ValueHint};
+use image::{GrayImage, ImageBuffer, Pixel, RgbImage};
+use img_hash::{HashAlg, Hasher, HasherConfig, ImageHash};
 use rayon::prelude::*;
-use slidesplit::{cluster_frames, merge_short_clusters, FrameEntry};
+use slidesplit::{
+    cluster_span, dedupe_clusters, detect_content_area, detect_dynamic_regions, detect_screen_quad,
+    diagnose_clusters, estimate_threshold, fit_slide_count, fuse_frames, laplacian_variance,
+    merge_short_clusters, parse_timestamp, parse_truth, score_boundaries, select_representatives,
+    split_builds, AnchorStrategy, ChangePointStrategy, ClusterStrategy, Corners, FrameEntry,
+    FrameFusion, HashFusion, MultiHash, Quad, Rect, RegionMask, RepresentativeRule,
+    SlidingWindowStrategy,
+};
 use std::ffi::OsStr;
+use std::fmt;
 use std::fs;
+use std::io::{BufRead, BufReader, Read};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
+use std::sync::Arc;
 use tempfile::TempDir;
-use tracing::{debug, error, info, instrument, warn};
+use tracing::{debug, error, info, instrument, trace, warn};
 use walkdir::WalkDir;
 
 /// Output image formats (note: jpg/jpeg are NOT lossless).
 #[derive(Clone, Copy, Debug, ValueEnum)]
-enum OutFormat {
+pub enum OutFormat {
     Png,
     Webp,
     Tiff,
@@ -32,8 +43,190 @@ impl OutFormat {
             OutFormat::Jpg | OutFormat::Jpeg => "jpg",
         }
     }
-    fn is_lossless_default(self) -> bool {
-        matches!(self, OutFormat::Png | OutFormat::Tiff | OutFormat::Bmp)
+    /// Format-specific ffmpeg encoder options (lossless flags, compression, quality)
+    fn encoder_args(self, webp_lossless: bool) -> &'static [&'static str] {
+        match self {
+            OutFormat::Webp if webp_lossless => &["-lossless", "1"],
+            OutFormat::Tiff => &["-compression_algo", "lzw"],
+            OutFormat::Png => &["-compression_level", "12"],
+            OutFormat::Jpg | OutFormat::Jpeg => &["-qscale:v", "2"],
+            OutFormat::Webp | OutFormat::Bmp => &[],
+        }
+    }
+}
+
+/// Initial clustering strategies selectable from the CLI.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Strategy {
+    /// Compare each frame to the first frame of its cluster (hard cuts, clean captures)
+    Anchor,
+    /// Compare each frame to the rolling mean of recent hashes (gradual drift, camera recordings)
+    SlidingWindow,
+    /// Optimal change-point segmentation of the hash sequence (slow cross-fades)
+    ChangePoint,
+}
+
+/// Slide boundary detectors selectable from the CLI
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Detector {
+    /// Hash every sampled frame and cluster the hashes
+    Phash,
+    /// Split at ffmpeg scene changes and hash one frame per scene
+    Scene,
+    /// Hash one frame per frozen interval found by ffmpeg `freezedetect`
+    Freeze,
+}
+
+/// Perceptual hash types, all 8x8 = 64 bits (see img_hash's `HashAlg`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum HashKind {
+    /// Gradient hash (dHash), the default single hash
+    Gradient,
+    /// Mean of DCT coefficients (pHash), robust to compression noise
+    Dct,
+    /// Mean hash (aHash), cheap but sensitive to brightness changes
+    Mean,
+    /// Blockhash, sensitive to small local edits such as text changes
+    Blockhash,
+}
+
+impl HashKind {
+    fn hasher(self) -> Hasher {
+        let config = HasherConfig::new().hash_size(8, 8);
+        match self {
+            HashKind::Gradient => config,
+            HashKind::Dct => config.hash_alg(HashAlg::Mean).preproc_dct(),
+            HashKind::Mean => config.hash_alg(HashAlg::Mean),
+            HashKind::Blockhash => config.hash_alg(HashAlg::Blockhash),
+        }
+        .to_hasher()
+    }
+}
+
+/// How the distances of multiple hash types are combined
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Fusion {
+    /// Weighted mean of the distances
+    Weighted,
+    /// Weighted majority vote on each boundary
+    Vote,
+}
+
+impl From<Fusion> for HashFusion {
+    fn from(f: Fusion) -> Self {
+        match f {
+            Fusion::Weighted => HashFusion::Weighted,
+            Fusion::Vote => HashFusion::Vote,
+        }
+    }
+}
+
+/// What to write for slides built up incrementally (animated bullet reveals)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Builds {
+    /// No build detection: build steps are split or merged by the clustering rules
+    Off,
+    /// Only the final, fully built state of each slide
+    Final,
+    /// Every build step, as slide_NN_step_MM
+    Steps,
+    /// The final state, with the timing of every step recorded in builds.csv
+    Timed,
+}
+
+/// How the frame written for each slide is chosen
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Representative {
+    /// The middle frame of the slide
+    Median,
+    /// The sharpest frame (highest Laplacian variance), avoids blurred or fading frames
+    Sharpest,
+    /// The last frame still matching the slide, after cursors and annotations settle
+    LastStable,
+    /// The frame closest to the slide's hash centroid
+    Centroid,
+}
+
+impl From<Representative> for RepresentativeRule {
+    fn from(r: Representative) -> Self {
+        match r {
+            Representative::Median => RepresentativeRule::Median,
+            Representative::Sharpest => RepresentativeRule::Sharpest,
+            Representative::LastStable => RepresentativeRule::LastStable,
+            Representative::Centroid => RepresentativeRule::Centroid,
+        }
+    }
+}
+
+/// Pixel-wise fusion of a slide's frames into one clean image
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum FuseFrames {
+    /// Per-pixel median: removes cursors, laser pointer dots and other transient overlays
+    Median,
+    /// Per-pixel trimmed mean: also averages out compression and camera noise
+    TrimmedMean,
+}
+
+impl From<FuseFrames> for FrameFusion {
+    fn from(f: FuseFrames) -> Self {
+        match f {
+            FuseFrames::Median => FrameFusion::Median,
+            FuseFrames::TrimmedMean => FrameFusion::TrimmedMean,
+        }
+    }
+}
+
+/// Hamming distance threshold: a fixed value or estimated from the video
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Threshold {
+    Fixed(u32),
+    Auto,
+}
+
+/// Used when `--threshold auto` finds nothing to split (e.g. a single static slide)
+const FALLBACK_THRESHOLD: u32 = 10;
+
+impl Threshold {
+    /// Resolve to a concrete distance, estimating it from the frame hashes if needed
+    fn resolve(self, frames: &[FrameEntry]) -> u32 {
+        match self {
+            Threshold::Fixed(t) => t,
+            Threshold::Auto => match estimate_threshold(frames) {
+                Some(t) => {
+                    info!("Estimated threshold from distance distribution: {}", t);
+                    t
+                }
+                None => {
+                    warn!(
+                        "Could not estimate a threshold (no distance variation), using {}",
+                        FALLBACK_THRESHOLD
+                    );
+                    FALLBACK_THRESHOLD
+                }
+            },
+        }
+    }
+}
+
+impl std::str::FromStr for Threshold {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(Threshold::Auto);
+        }
+        s.parse::<u32>()
+            .map(Threshold::Fixed)
+            .map_err(|_| format!("expected an integer 0..=64 or \"auto\", got: {}", s))
+    }
+}
+
+impl fmt::Display for Threshold {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Threshold::Fixed(t) => write!(f, "{}", t),
+            Threshold::Auto => f.write_str("auto"),
+        }
     }
 }
 
@@ -46,10 +239,66 @@ pub struct Config {
     pub out_dir: PathBuf,
     /// Sampling frames per second before de-duplication
     pub fps: f32,
-    /// Hamming distance threshold (0..=64) to separate slides
-    pub threshold: u32,
+    /// Hamming distance threshold (0..=64 or auto) to separate slides
+    pub threshold: Threshold,
     /// Minimum stable duration in seconds to accept a slide
     pub min_stable_seconds: f32,
+    /// Slide boundary detector
+    pub detector: Detector,
+    /// Scene score (0..1) above which ffmpeg reports a scene change
+    pub scene_threshold: f32,
+    /// Noise tolerance of `freezedetect`, as a ratio (0..1) of the pixel range
+    pub freeze_noise: f32,
+    /// Minimum duration in seconds of a frozen interval
+    pub freeze_duration: f32,
+    /// Initial clustering strategy
+    pub strategy: Strategy,
+    /// Number of recent hashes averaged by the sliding-window strategy
+    pub window: usize,
+    /// Per-segment penalty (in hash bits) for the change-point strategy
+    pub penalty: f64,
+    /// Write revisited slides once and record all their time ranges
+    pub dedupe: bool,
+    /// Handling of incremental slide builds
+    pub builds: Builds,
+    /// Write per-slide confidence scores and boundary diagnostics
+    pub diagnostics: bool,
+    /// Hash types computed per frame; the first one is the primary hash
+    pub hashes: Vec<HashKind>,
+    /// Weight of each hash type, in `hashes` order
+    pub hash_weights: Arc<[f32]>,
+    /// Combination rule when more than one hash type is used
+    pub fusion: Fusion,
+    /// How the representative frame of each slide is chosen
+    pub representative: Representative,
+    /// Number of representative frames written per slide
+    pub representatives: usize,
+    /// Write each slide as a fusion of its frames instead of copying representatives
+    pub fuse_frames: Option<FuseFrames>,
+    /// Regions of the frame included in or excluded from hashing
+    pub mask: RegionMask,
+    /// Crop the written slides to the include regions
+    pub crop_output: bool,
+    /// Detect constantly changing regions and exclude them from hashing
+    pub auto_mask: bool,
+    /// Crop letterbox/pillarbox bars from the hashed frames and written slides
+    pub autocrop: bool,
+    /// Detect the projector screen and rectify it to a front-on slide
+    pub rectify: bool,
+    /// Screen corners given by the user (implies rectification)
+    pub corners: Option<Corners>,
+    /// Directory caching extracted frames and hashes between runs
+    pub cache_dir: Option<PathBuf>,
+    /// Known number of slides: search the threshold and min-stable duration producing it
+    pub expect_slides: Option<usize>,
+    /// Decode only keyframes instead of sampling at `fps`
+    pub keyframes_only: bool,
+    /// With `keyframes_only`: densely decode the gaps at slide changes
+    pub refine: bool,
+    /// Decode sampled frames from an ffmpeg rawvideo pipe instead of image files
+    pub pipe: bool,
+    /// Width of the grayscale frames decoded for hashing (implies `pipe`)
+    pub hash_width: Option<u32>,
     /// Keep temporary extracted frames
     pub keep_temps: bool,
     /// Output format
@@ -63,27 +312,114 @@ pub struct Config {
 impl Config {
     /// Create config from CLI args, with validation and defaults applied
     #[instrument(name = "config_from_args")]
-    pub fn from_args(args: Args) -> Result<Self> {
+    fn from_args(args: Args) -> Result<Self> {
         // Validate input file exists
-        if !args.input.exists() {
-            return Err(anyhow!("Input file not found: {}", args.input.display()));
+        let input = args.input.ok_or_else(|| anyhow!("An input video is required"))?;
+        if !input.exists() {
+            return Err(anyhow!("Input file not found: {}", input.display()));
         }
 
         // Determine output directory
         let out_dir = args
             .out_dir
-            .unwrap_or_else(|| default_out_dir(&args.input));
+            .unwrap_or_else(|| default_out_dir(&input));
 
         // Validate parameters
         if args.fps <= 0.0 {
             return Err(anyhow!("FPS must be positive, got: {}", args.fps));
         }
-        if args.threshold > 64 {
-            return Err(anyhow!("Threshold must be 0..=64, got: {}", args.threshold));
+        if let Threshold::Fixed(t) = args.threshold {
+            if t > 64 {
+                return Err(anyhow!("Threshold must be 0..=64, got: {}", t));
+            }
         }
         if args.min_stable_seconds < 0.0 {
             return Err(anyhow!("min_stable_seconds must be non-negative, got: {}", args.min_stable_seconds));
         }
+        if !(0.0..=1.0).contains(&args.scene_threshold) {
+            return Err(anyhow!("Scene threshold must be 0..=1, got: {}", args.scene_threshold));
+        }
+        if !(0.0..=1.0).contains(&args.freeze_noise) {
+            return Err(anyhow!("Freeze noise must be 0..=1, got: {}", args.freeze_noise));
+        }
+        if args.freeze_duration <= 0.0 {
+            return Err(anyhow!("Freeze duration must be positive, got: {}", args.freeze_duration));
+        }
+        if args.window == 0 {
+            return Err(anyhow!("Window must be at least 1 frame, got: {}", args.window));
+        }
+        if args.penalty < 0.0 {
+            return Err(anyhow!("Penalty must be non-negative, got: {}", args.penalty));
+        }
+        if args.expect_slides == Some(0) {
+            return Err(anyhow!("--expect-slides must be at least 1"));
+        }
+        if args.representatives == 0 {
+            return Err(anyhow!("At least one representative per slide is required"));
+        }
+        if args.hashes.is_empty() {
+            return Err(anyhow!("At least one hash type is required"));
+        }
+        let hash_weights: Arc<[f32]> = if args.hash_weights.is_empty() {
+            vec![1.0; args.hashes.len()].into()
+        } else if args.hash_weights.len() != args.hashes.len() {
+            return Err(anyhow!(
+                "Expected {} hash weights (one per hash type), got: {}",
+                args.hashes.len(),
+                args.hash_weights.len()
+            ));
+        } else if args.hash_weights.iter().any(|&w| w < 0.0)
+            || args.hash_weights.iter().sum::<f32>() <= 0.0
+        {
+            return Err(anyhow!("Hash weights must be non-negative with a positive sum"));
+        } else {
+            args.hash_weights.into()
+        };
+
+        if args.refine && !args.keyframes_only {
+            return Err(anyhow!("--refine requires --keyframes-only"));
+        }
+        if args.keyframes_only && args.detector != Detector::Phash {
+            warn!(
+                "--keyframes-only is ignored with --detector {:?}, which samples at --fps",
+                args.detector
+            );
+        }
+        if args.hash_width.is_some_and(|w| w < 16) {
+            return Err(anyhow!("Hash width must be at least 16 pixels"));
+        }
+        let pipe = args.pipe || args.hash_width.is_some();
+        if pipe && args.cache_dir.is_some() {
+            return Err(anyhow!(
+                "--pipe and --hash-width cannot be combined with --cache-dir: piped frames are \
+                 not stored"
+            ));
+        }
+        if pipe && args.detector != Detector::Phash {
+            warn!(
+                "--pipe is ignored with --detector {:?}, which extracts one frame per interval",
+                args.detector
+            );
+        } else if pipe {
+            let reads_all_frames = [
+                (args.representative == Representative::Sharpest, "--representative sharpest"),
+                (args.builds != Builds::Off, "--builds"),
+                (args.fuse_frames.is_some(), "--fuse-frames"),
+            ];
+            if let Some((_, flag)) = reads_all_frames.iter().find(|(set, _)| *set) {
+                return Err(anyhow!(
+                    "--pipe and --hash-width cannot be combined with {}, which reads every \
+                     frame of a slide: piped frames are not stored",
+                    flag
+                ));
+            }
+        }
+        if args.crop_output && args.include.is_empty() {
+            return Err(anyhow!("--crop-output requires at least one --include region"));
+        }
+        if args.fuse_frames.is_some() && args.representatives > 1 {
+            warn!("--representatives is ignored with --fuse-frames: each slide is fused into one image");
+        }
 
         // Warn about lossy formats
         if matches!(args.format, OutFormat::Jpg | OutFormat::Jpeg) {
@@ -94,29 +430,246 @@ impl Config {
         let ffmpeg_bin = ensure_ffmpeg_available()?;
 
         info!("Configuration initialized");
-        debug!("Config: input={}, out_dir={}, fps={}, threshold={}", 
-               args.input.display(), out_dir.display(), args.fps, args.threshold);
+        debug!("Config: input={}, out_dir={}, fps={}, threshold={}, strategy={:?}", 
+               input.display(), out_dir.display(), args.fps, args.threshold, args.strategy);
 
         Ok(Config {
-            input: args.input,
+            input,
             out_dir,
             fps: args.fps,
             threshold: args.threshold,
             min_stable_seconds: args.min_stable_seconds,
+            detector: args.detector,
+            scene_threshold: args.scene_threshold,
+            freeze_noise: args.freeze_noise,
+            freeze_duration: args.freeze_duration,
+            strategy: args.strategy,
+            window: args.window,
+            penalty: args.penalty,
+            dedupe: args.dedupe,
+            builds: args.builds,
+            diagnostics: args.diagnostics,
+            hashes: args.hashes,
+            hash_weights,
+            fusion: args.fusion,
+            representative: args.representative,
+            representatives: args.representatives,
+            fuse_frames: args.fuse_frames,
+            mask: RegionMask { include: args.include, exclude: args.exclude },
+            crop_output: args.crop_output,
+            auto_mask: args.auto_mask,
+            autocrop: !args.no_autocrop,
+            rectify: args.rectify,
+            corners: args.corners,
+            cache_dir: args.cache_dir,
+            expect_slides: args.expect_slides,
+            keyframes_only: args.keyframes_only,
+            refine: args.refine,
+            pipe: args.pipe || args.hash_width.is_some(),
+            hash_width: args.hash_width,
             keep_temps: args.keep_temps,
             format: args.format,
             webp_lossless: args.webp_lossless,
             ffmpeg_bin,
         })
     }
+
+    /// ffmpeg input options selecting the decoded frames
+    fn decode_args(&self) -> &'static [&'static str] {
+        if self.keyframes_only {
+            &["-skip_frame", "nokey"]
+        } else {
+            &[]
+        }
+    }
+
+    /// Filter chain sampling the decoded frames (at `fps`, or every keyframe)
+    /// followed by `rest`
+    fn sample_filter(&self, rest: &str) -> String {
+        if self.keyframes_only {
+            rest.to_string()
+        } else {
+            format!("{},{}", self.fps_select(), rest)
+        }
+    }
+
+    /// `select` filter keeping the first decoded frame of every `1 / fps` interval.
+    ///
+    /// Unlike the `fps` filter this passes the frames through unchanged, so a
+    /// following `showinfo` reports their real presentation times instead of
+    /// times on a constant frame rate grid.
+    fn fps_select(&self) -> String {
+        format!(
+            "select='isnan(prev_selected_t)+gt(floor(t*{fps}),floor(prev_selected_t*{fps}))'",
+            fps = self.fps
+        )
+    }
+
+    /// Build the library clustering strategy selected on the command line
+    fn cluster_strategy(&self) -> Box<dyn ClusterStrategy + Send + Sync> {
+        match self.strategy {
+            Strategy::Anchor => Box::new(AnchorStrategy),
+            Strategy::SlidingWindow => Box::new(SlidingWindowStrategy { window: self.window }),
+            Strategy::ChangePoint => Box::new(ChangePointStrategy { penalty: self.penalty }),
+        }
+    }
 }
 
 #[derive(Parser, Debug)]
-#[command(author, version, about)]
+#[command(
+    author,
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    #[command(flatten)]
+    args: Args,
+}
+
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Extract and hash once, then report the number of slides found over a grid
+    /// of thresholds and minimum stable durations
+    Tune(TuneArgs),
+    /// Score the detected slide changes against ground-truth timings (precision,
+    /// recall and F1 of the boundaries, slide count error)
+    Eval(EvalArgs),
+    /// Render a synthetic slideshow video with known slide change times, for
+    /// regression corpora and bug reports
+    Synth(SynthArgs),
+}
+
+/// Slide transitions rendered by `synth`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Transition {
+    /// Hard cut
+    Cut,
+    /// Cross-fade
+    Fade,
+    /// The next slide is uncovered from right to left
+    Wipe,
+    /// The next slide pushes the current one out to the left
+    Push,
+}
+
+impl Transition {
+    /// Name of the ffmpeg `xfade` transition (none for hard cuts)
+    fn xfade(self) -> Option<&'static str> {
+        match self {
+            Transition::Cut => None,
+            Transition::Fade => Some("fade"),
+            Transition::Wipe => Some("wipeleft"),
+            Transition::Push => Some("slideleft"),
+        }
+    }
+}
+
+#[derive(clap::Args, Debug)]
+struct SynthArgs {
+    /// Output video file (e.g., deck.mp4)
+    #[arg(value_hint = ValueHint::FilePath)]
+    output: PathBuf,
+
+    /// Ground-truth file with the slide change times. Defaults to "<output_stem>_truth.csv"
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    truth: Option<PathBuf>,
+
+    /// Number of slides
+    #[arg(long, default_value_t = 6)]
+    slides: usize,
+
+    /// Seconds each slide is shown, comma-separated; the list is repeated as needed
+    #[arg(long, value_delimiter = ',', default_values_t = [4.0])]
+    durations: Vec<f32>,
+
+    /// Transition between slides: cut, fade, wipe, push
+    #[arg(long, value_enum, default_value_t = Transition::Cut)]
+    transition: Transition,
+
+    /// Duration of each transition in seconds (not used for cuts)
+    #[arg(long, default_value_t = 0.5)]
+    transition_duration: f32,
+
+    /// Overlay a mouse cursor wandering over the slides
+    #[arg(long, action = ArgAction::SetTrue)]
+    cursor: bool,
+
+    /// Overlay a constantly changing webcam tile in the bottom-right corner
+    #[arg(long, action = ArgAction::SetTrue)]
+    webcam: bool,
+
+    /// x264 constant rate factor: 0 is lossless, 51 the strongest compression
+    #[arg(long, default_value_t = 23)]
+    crf: u32,
+
+    /// Video width in pixels
+    #[arg(long, default_value_t = 960)]
+    width: u32,
+
+    /// Video height in pixels
+    #[arg(long, default_value_t = 540)]
+    height: u32,
+
+    /// Video frame rate
+    #[arg(long, default_value_t = 25)]
+    fps: u32,
+
+    /// Seed of the slide layouts; the same seed renders the same deck
+    #[arg(long, default_value_t = 1)]
+    seed: u64,
+
+    /// Set logging level: error, warn, info, debug, trace
+    #[arg(short, long, default_value = "info")]
+    verbosity: String,
+}
+
+#[derive(clap::Args, Debug)]
+struct EvalArgs {
+    #[command(flatten)]
+    args: Args,
+
+    /// Ground truth: one slide change time per line, in seconds or [HH:]MM:SS
+    /// (only the first comma-separated column is read)
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    truth: PathBuf,
+
+    /// Maximum distance in seconds between a detected and a true slide change
+    #[arg(long, default_value_t = 1.0)]
+    tolerance: f64,
+}
+
+#[derive(clap::Args, Debug)]
+struct TuneArgs {
+    #[command(flatten)]
+    args: Args,
+
+    /// Thresholds to try, comma-separated
+    #[arg(long, value_delimiter = ',', default_values_t = [4, 6, 8, 10, 12, 16, 20])]
+    thresholds: Vec<u32>,
+
+    /// Minimum stable durations in seconds to try, comma-separated
+    #[arg(long, value_delimiter = ',', default_values_t = [0.5, 1.0, 2.0, 3.0])]
+    min_stable: Vec<f32>,
+
+    /// Also write the table to this CSV file
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    csv: Option<PathBuf>,
+
+    /// Also write the table to this JSON file
+    #[arg(long, value_hint = ValueHint::FilePath)]
+    json: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
 struct Args {
     /// Input video file (e.g., slideshow.mp4)
-    #[arg(value_hint = ValueHint::FilePath)]
-    input: PathBuf,
+    #[arg(required = true, value_hint = ValueHint::FilePath)]
+    input: Option<PathBuf>,
 
     /// Output directory (created if missing). Defaults to "<input_stem>_slides"
     #[arg(short, long, value_hint = ValueHint::DirPath)]
@@ -126,15 +679,162 @@ struct Args {
     #[arg(long, default_value_t = 2.0)]
     fps: f32,
 
-    /// Hamming distance threshold (0..=64) to separate slides
-    #[arg(long, default_value_t = 10)]
-    threshold: u32,
+    /// Hamming distance threshold (0..=64) to separate slides, or "auto" to estimate it
+    /// from the distribution of frame-to-frame distances
+    #[arg(long, default_value_t = Threshold::Fixed(10))]
+    threshold: Threshold,
 
     /// Minimum stable duration in seconds to accept a slide (merges transition blobs)
     #[arg(long, default_value_t = 1.0)]
     min_stable_seconds: f32,
 
-    /// Keep temporary extracted frames
+    /// Slide boundary detector: phash hashes every sampled frame; scene splits at
+    /// ffmpeg scene changes and hashes one frame per scene (faster on long videos);
+    /// freeze hashes one frame per frozen interval found by ffmpeg freezedetect
+    #[arg(long, value_enum, default_value_t = Detector::Phash)]
+    detector: Detector,
+
+    /// For the scene detector only: ffmpeg scene score (0..1) that starts a new scene
+    #[arg(long, default_value_t = 0.1)]
+    scene_threshold: f32,
+
+    /// For the freeze detector only: pixel noise tolerated within a frozen interval,
+    /// as a ratio of the pixel range (ffmpeg freezedetect n)
+    #[arg(long, default_value_t = 0.003)]
+    freeze_noise: f32,
+
+    /// For the freeze detector only: minimum seconds without change for a frozen
+    /// interval (ffmpeg freezedetect d)
+    #[arg(long, default_value_t = 1.0)]
+    freeze_duration: f32,
+
+    /// Initial clustering strategy: anchor, sliding-window, change-point
+    #[arg(long, value_enum, default_value_t = Strategy::Anchor)]
+    strategy: Strategy,
+
+    /// For sliding-window only: number of recent hashes in the rolling mean
+    #[arg(long, default_value_t = 5)]
+    window: usize,
+
+    /// For change-point only: cost of starting a new segment, in hash bits
+    /// (higher = fewer slides; --threshold is not used)
+    #[arg(long, default_value_t = 20.0)]
+    penalty: f64,
+
+    /// Detect slides shown more than once (e.g. the presenter jumps back) and write them
+    /// once; every time range is recorded in slides.csv
+    #[arg(long, action = ArgAction::SetTrue)]
+    dedupe: bool,
+
+    /// Detect incremental slide builds and write: off, final, steps, timed
+    #[arg(long, value_enum, default_value_t = Builds::Off)]
+    builds: Builds,
+
+    /// Score each slide's confidence (boundary strength, hash spread, merges), write
+    /// diagnostics.csv and list the slides worth a manual check
+    #[arg(long, action = ArgAction::SetTrue)]
+    diagnostics: bool,
+
+    /// Hash types computed per frame, comma-separated: gradient, dct, mean, blockhash.
+    /// The first one is the primary hash; listing several fuses their distances
+    #[arg(long, value_enum, value_delimiter = ',', default_values_t = [HashKind::Gradient])]
+    hashes: Vec<HashKind>,
+
+    /// Weight of each hash type in --hashes order, comma-separated (default: all 1)
+    #[arg(long, value_delimiter = ',')]
+    hash_weights: Vec<f32>,
+
+    /// How distances of several hash types are combined: weighted, vote
+    #[arg(long, value_enum, default_value_t = Fusion::Weighted)]
+    fusion: Fusion,
+
+    /// Representative frame of each slide: median, sharpest, last-stable, centroid
+    #[arg(long, value_enum, default_value_t = Representative::Median)]
+    representative: Representative,
+
+    /// Number of representative frames per slide; more than one writes
+    /// slide_NN_rep_MM files spread according to --representative
+    #[arg(long, default_value_t = 1)]
+    representatives: usize,
+
+    /// Build each slide image from all its frames instead of copying one: median
+    /// (removes cursors and pointers) or trimmed-mean (also removes noise)
+    #[arg(long, value_enum)]
+    fuse_frames: Option<FuseFrames>,
+
+    /// Only hash this region, as X,Y,W,H in pixels (integers) or fractions of the
+    /// frame (decimals, e.g. 0.0,0.0,0.75,1.0); repeat for several regions
+    #[arg(long, value_name = "X,Y,W,H")]
+    include: Vec<Rect>,
+
+    /// Ignore this region when hashing (webcam tile, clock), same syntax as --include;
+    /// repeat for several regions
+    #[arg(long, value_name = "X,Y,W,H")]
+    exclude: Vec<Rect>,
+
+    /// Crop the written slides to the bounding box of the --include regions
+    /// (slides are full-frame by default)
+    #[arg(long, action = ArgAction::SetTrue)]
+    crop_output: bool,
+
+    /// Detect regions that keep changing while the slide is stable (webcam tiles,
+    /// tickers, clocks) and exclude them from hashing; the mask is saved as mask.png
+    #[arg(long, action = ArgAction::SetTrue)]
+    auto_mask: bool,
+
+    /// Keep letterbox/pillarbox bars: by default black bars around the content are
+    /// detected and cropped from both the hashed frames and the written slides
+    #[arg(long, action = ArgAction::SetTrue)]
+    no_autocrop: bool,
+
+    /// For camera-recorded projector screens: detect the bright screen and warp it
+    /// to a front-on slide before hashing and output
+    #[arg(long, action = ArgAction::SetTrue)]
+    rectify: bool,
+
+    /// Screen corners top-left, top-right, bottom-right, bottom-left, in pixels or
+    /// fractions of the frame; rectifies without detection
+    #[arg(long, value_name = "X1,Y1,X2,Y2,X3,Y3,X4,Y4")]
+    corners: Option<Corners>,
+
+    /// Cache extracted frames and hashes in this directory, keyed by a hash of the
+    /// full input file contents (read once per run) and of the extraction and hash
+    /// settings; later runs that only change clustering or output options skip
+    /// ffmpeg and hashing
+    #[arg(long, value_hint = ValueHint::DirPath)]
+    cache_dir: Option<PathBuf>,
+
+    /// The video is known to have N slides (e.g. from the original PDF): search the
+    /// threshold and min-stable duration that produce N, overriding --threshold and
+    /// --min-stable-seconds
+    #[arg(long, value_name = "N")]
+    expect_slides: Option<usize>,
+
+    /// Fast scan: decode only keyframes (I-frames) instead of sampling at --fps.
+    /// Rough, as slide changes are only seen at the next keyframe, but much faster on
+    /// recordings with long GOPs or keyframes at slide changes
+    #[arg(long, action = ArgAction::SetTrue)]
+    keyframes_only: bool,
+
+    /// With --keyframes-only: decode the gap between the keyframes around each slide
+    /// change at --fps to find the change precisely
+    #[arg(long, action = ArgAction::SetTrue)]
+    refine: bool,
+
+    /// Read the sampled frames from an ffmpeg rawvideo pipe and hash them in memory
+    /// instead of writing every frame to disk; only the representative frames are
+    /// extracted from the video (not available with --representative sharpest,
+    /// --builds or --fuse-frames)
+    #[arg(long, action = ArgAction::SetTrue)]
+    pipe: bool,
+
+    /// Hash grayscale frames downscaled by ffmpeg to this width (e.g. 320) instead of
+    /// full-resolution ones; implies --pipe, slides are still extracted at full resolution
+    #[arg(long, value_name = "PX")]
+    hash_width: Option<u32>,
+
+    /// Keep temporary extracted frames in frames_raw, and the frames decoded around
+    /// slide changes by --refine in frames_raw/refine_NNNN
     #[arg(long, action = ArgAction::SetTrue)]
     keep_temps: bool,
 
@@ -152,18 +852,31 @@ struct Args {
 }
 
 fn main() -> Result<()> {
-    let args = Args::parse();
+    let cli = Cli::parse();
+    let verbosity = match &cli.command {
+        Some(Commands::Tune(tune)) => &tune.args.verbosity,
+        Some(Commands::Eval(eval)) => &eval.args.verbosity,
+        Some(Commands::Synth(synth)) => &synth.verbosity,
+        None => &cli.args.verbosity,
+    };
     
     // Initialize structured logging
-    init_logging(&args.verbosity)?;
+    init_logging(verbosity)?;
     
     info!("Starting slidesplit v{}", env!("CARGO_PKG_VERSION"));
 
-    // Create configuration with validation
-    let config = Config::from_args(args)?;
+    match cli.command {
+        Some(Commands::Tune(tune)) => tune_settings(tune)?,
+        Some(Commands::Eval(eval)) => evaluate(eval)?,
+        Some(Commands::Synth(synth)) => synthesize(synth)?,
+        None => {
+            // Create configuration with validation
+            let config = Config::from_args(cli.args)?;
 
-    // Run the main processing pipeline
-    process_video(config)?;
+            // Run the main processing pipeline
+            process_video(config)?;
+        }
+    }
 
     info!("Processing completed successfully");
     Ok(())
@@ -198,38 +911,31 @@ fn process_video(config: Config) -> Result<()> {
     fs::create_dir_all(&config.out_dir)
         .with_context(|| format!("Failed to create output directory: {}", config.out_dir.display()))?;
 
-    let frames_dir = TempDir::new().context("Failed to create temporary directory for frames")?;
-    debug!("Created temporary directory: {}", frames_dir.path().display());
-
-    // Extract frames
-    extract_frames(&config, frames_dir.path())?;
+    let temp_dir = TempDir::new().context("Failed to create temporary directory for frames")?;
+    let (frames, prep, frames_dir) = load_frames(&config, temp_dir.path())?;
+    let Segmentation { threshold, initial, clusters, mut slides } =
+        segment_frames(&config, &frames)?;
 
-    // Load and hash frames
-    let frames = load_frame_hashes(frames_dir.path())?;
-    if frames.is_empty() {
-        return Err(anyhow!("No frames extracted. Is the video valid?"));
+    // Group revisited slides
+    if config.dedupe {
+        slides = dedupe_slides(&clusters, &slides, &frames, threshold);
     }
-    info!("Loaded {} frames for processing", frames.len());
-
-    // Cluster frames
-    let mut clusters = cluster_frames(&frames, config.threshold);
-    info!("Initial clustering produced {} clusters", clusters.len());
-    
-    merge_short_clusters(
-        &mut clusters,
-        &frames,
-        config.min_stable_seconds,
-        config.fps,
-        config.threshold,
-    );
-    info!("After merging short clusters: {} final clusters", clusters.len());
 
     // Write output slides
-    let wrote = write_output_slides(&config, &clusters, &frames)?;
+    let wrote = write_output_slides(&config, &prep, &clusters, &slides, &frames, threshold)?;
+    if config.dedupe {
+        write_slide_ranges(&config, &clusters, &slides, &frames)?;
+    }
+    if config.builds == Builds::Timed {
+        write_build_timings(&config, &clusters, &slides, &frames)?;
+    }
+    if config.diagnostics {
+        write_diagnostics(&config, &initial, &clusters, &slides, &frames, threshold)?;
+    }
 
     // Optionally keep temporary frames
     if config.keep_temps {
-        keep_temporary_frames(&config, frames_dir.path())?;
+        keep_temporary_frames(&config, &frames_dir, temp_dir.path())?;
     }
 
     info!("Done. Wrote {} slide{} to {}", 
@@ -239,59 +945,1139 @@ fn process_video(config: Config) -> Result<()> {
     Ok(())
 }
 
-/// Write representative frames for each cluster to output directory
-#[instrument(name = "write_output", skip(config, clusters, frames))]
-fn write_output_slides(config: &Config, clusters: &[Vec<usize>], frames: &[FrameEntry]) -> Result<usize> {
+/// Frames grouped into slides
+struct Segmentation {
+    /// Threshold used (resolved from `auto` or fitted by `--expect-slides`)
+    threshold: u32,
+    /// Clusters before merging short ones
+    initial: Vec<Vec<usize>>,
+    /// Final clusters: build steps with `--builds`, otherwise one per slide
+    clusters: Vec<Vec<usize>>,
+    /// Cluster indices of each slide, consecutive and in time order
+    slides: Vec<Vec<usize>>,
+}
+
+impl Segmentation {
+    /// Times in seconds at which a new slide appears (the first slide excluded)
+    fn boundaries(&self, frames: &[FrameEntry]) -> Vec<f64> {
+        self.slides
+            .iter()
+            .skip(1)
+            .filter_map(|slide| {
+                let cluster = &self.clusters[*slide.first()?];
+                (!cluster.is_empty()).then(|| cluster_span(cluster, frames).0)
+            })
+            .collect()
+    }
+}
+
+/// Cluster the frames, merge short clusters and recognize builds
+fn segment_frames(config: &Config, frames: &[FrameEntry]) -> Result<Segmentation> {
+    let (threshold, min_stable_seconds) = match config.expect_slides {
+        Some(target) => fit_expected_slides(config, frames, target),
+        None => (config.threshold.resolve(frames), config.min_stable_seconds),
+    };
+
+    let (initial, clusters) = cluster_and_merge(config, frames, threshold, min_stable_seconds);
+
+    // Recognize builds: clusters become build steps, grouped per slide
+    let (clusters, slides) = if config.builds == Builds::Off {
+        let slides = (0..clusters.len()).map(|i| vec![i]).collect();
+        (clusters, slides)
+    } else {
+        let thumbs = load_thumbnails(frames)?;
+        let mut steps: Vec<Vec<usize>> = Vec::new();
+        let mut slides: Vec<Vec<usize>> = Vec::new();
+        for build in split_builds(&clusters, &thumbs) {
+            slides.push((steps.len()..steps.len() + build.len()).collect());
+            steps.extend(build);
+        }
+        info!("Build detection found {} slides with {} build steps", slides.len(), steps.len());
+        (steps, slides)
+    };
+
+    Ok(Segmentation { threshold, initial, clusters, slides })
+}
+
+/// Cluster the frames with the configured strategy and merge short clusters,
+/// returning the initial and the merged clusters
+fn cluster_and_merge(
+    config: &Config,
+    frames: &[FrameEntry],
+    threshold: u32,
+    min_stable_seconds: f32,
+) -> (Vec<Vec<usize>>, Vec<Vec<usize>>) {
+    let mut clusters = config.cluster_strategy().cluster(frames, threshold);
+    info!("Initial clustering produced {} clusters", clusters.len());
+    let initial = clusters.clone();
+
+    merge_short_clusters(
+        &mut clusters,
+        frames,
+        min_stable_seconds,
+        threshold,
+    );
+    info!("After merging short clusters: {} final clusters", clusters.len());
+    (initial, clusters)
+}
+
+/// `eval` subcommand: score the detected slide changes against ground truth
+#[instrument(name = "eval", skip(eval))]
+fn evaluate(eval: EvalArgs) -> Result<()> {
+    if eval.tolerance < 0.0 {
+        return Err(anyhow!("Tolerance must be non-negative, got: {}", eval.tolerance));
+    }
+    let truth = read_truth(&eval.truth)?;
+    info!("Loaded {} true slide changes from {}", truth.len(), eval.truth.display());
+
+    let config = Config::from_args(eval.args)?;
+    let temp_dir = TempDir::new().context("Failed to create temporary directory for frames")?;
+    let (frames, _, _) = load_frames(&config, temp_dir.path())?;
+    let predicted = segment_frames(&config, &frames)?.boundaries(&frames);
+
+    let score = score_boundaries(&predicted, &truth, eval.tolerance);
+    println!(
+        "Boundaries within {}s: {} detected, {} true, {} matched",
+        eval.tolerance, score.predicted, score.truth, score.matched
+    );
+    println!(
+        "Precision {:.3}  Recall {:.3}  F1 {:.3}  Slide count error {:+}",
+        score.precision, score.recall, score.f1, score.count_error
+    );
+    Ok(())
+}
+
+/// Read slide change times from a truth file, see [`parse_truth`]
+fn read_truth(path: &Path) -> Result<Vec<f64>> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read truth file: {}", path.display()))?;
+    parse_truth(&text).map_err(|e| anyhow!("Failed to parse truth file {}: {}", path.display(), e))
+}
+
+/// `synth` subcommand: render a slideshow video and its ground-truth timings
+#[instrument(name = "synth", skip(synth))]
+fn synthesize(synth: SynthArgs) -> Result<()> {
+    if synth.slides == 0 {
+        return Err(anyhow!("At least one slide is required"));
+    }
+    if synth.durations.is_empty() || synth.durations.iter().any(|&d| d <= 0.0) {
+        return Err(anyhow!("Slide durations must be positive"));
+    }
+    let fade = match synth.transition.xfade() {
+        Some(_) => synth.transition_duration,
+        None => 0.0,
+    };
+    if fade < 0.0 || synth.durations.iter().any(|&d| fade >= d) {
+        return Err(anyhow!("Transition duration must be in 0..slide duration, got: {}", fade));
+    }
+    if synth.crf > 51 {
+        return Err(anyhow!("CRF must be 0..=51, got: {}", synth.crf));
+    }
+    if synth.width < 64 || synth.height < 64 || synth.width % 2 == 1 || synth.height % 2 == 1 {
+        return Err(anyhow!(
+            "Video size must be even and at least 64x64, got: {}x{}",
+            synth.width,
+            synth.height
+        ));
+    }
+    if synth.fps == 0 {
+        return Err(anyhow!("FPS must be positive"));
+    }
+
+    let ffmpeg_bin = ensure_ffmpeg_available()?;
+    let work = TempDir::new().context("Failed to create temporary directory for slides")?;
+    let mut rng = SplitMix64(synth.seed);
+    let durations: Vec<f32> = synth.durations.iter().copied().cycle().take(synth.slides).collect();
+
+    let mut cmd = Command::new(&ffmpeg_bin);
+    cmd.args(["-hide_banner", "-nostats", "-loglevel", "level+info", "-y"]);
+    let mut filters = Vec::new();
+    for (i, duration) in durations.iter().enumerate() {
+        let path = work.path().join(format!("slide_{:02}.png", i));
+        render_synthetic_slide(&mut rng, synth.width, synth.height)
+            .save(&path)
+            .with_context(|| format!("Failed to write synthetic slide: {}", path.display()))?;
+        // Each input also covers the transition into the next slide
+        cmd.args(["-loop", "1", "-framerate", &synth.fps.to_string()])
+            .args(["-t", &format!("{:.3}", duration + fade)])
+            .arg("-i")
+            .arg(&path);
+        filters.push(format!("[{}:v]settb=AVTB,fps={},format=yuv420p[s{}]", i, synth.fps, i));
+    }
+
+    // Chain the slides and record the middle of every transition
+    let mut truth = Vec::new();
+    let mut current = "s0".to_string();
+    match synth.transition.xfade() {
+        None => {
+            let inputs: String = (0..synth.slides).map(|i| format!("[s{}]", i)).collect();
+            filters.push(format!("{}concat=n={}:v=1:a=0[deck]", inputs, synth.slides));
+            current = "deck".to_string();
+            let mut t = 0.0;
+            for duration in &durations[..synth.slides - 1] {
+                t += duration;
+                truth.push(t);
+            }
+        }
+        Some(name) => {
+            let mut length = durations[0] + fade;
+            for (i, duration) in durations.iter().enumerate().skip(1) {
+                let offset = length - fade;
+                filters.push(format!(
+                    "[{}][s{}]xfade=transition={}:duration={:.3}:offset={:.3}[x{}]",
+                    current, i, name, fade, offset, i
+                ));
+                truth.push(offset + fade / 2.0);
+                length += duration;
+                current = format!("x{}", i);
+            }
+        }
+    }
+
+    let mut next_input = synth.slides;
+    if synth.cursor {
+        let path = work.path().join("cursor.png");
+        render_cursor()
+            .save(&path)
+            .with_context(|| format!("Failed to write cursor: {}", path.display()))?;
+        cmd.args(["-loop", "1", "-i"]).arg(&path);
+        // Slow Lissajous path, so the cursor both rests and crosses slides
+        filters.push(format!(
+            "[{}][{}:v]overlay=x=main_w*(0.5+0.35*sin(t*0.7)):\
+             y=main_h*(0.5+0.3*sin(t*0.45+1)):shortest=1[cur]",
+            current, next_input
+        ));
+        current = "cur".to_string();
+        next_input += 1;
+    }
+    if synth.webcam {
+        let size = format!("{}x{}", synth.width / 5 / 2 * 2, synth.height / 5 / 2 * 2);
+        cmd.args(["-f", "lavfi", "-i", &format!("testsrc2=size={}:rate={}", size, synth.fps)]);
+        filters.push(format!(
+            "[{}][{}:v]overlay=x=main_w-overlay_w-16:y=main_h-overlay_h-16:shortest=1[cam]",
+            current, next_input
+        ));
+        current = "cam".to_string();
+    }
+    filters.push(format!("[{}]format=yuv420p[out]", current));
+
+    cmd.args(["-filter_complex", &filters.join(";"), "-map", "[out]"])
+        .args(["-c:v", "libx264", "-crf", &synth.crf.to_string(), "-pix_fmt", "yuv420p"]);
+    // Keyframes exactly at the slide changes (and not wherever x264 detects a
+    // scene cut), so keyframe scans of the deck are deterministic
+    cmd.args(["-sc_threshold", "0"]);
+    if !truth.is_empty() {
+        let times: Vec<String> = truth.iter().map(|t| format!("{:.3}", t)).collect();
+        cmd.args(["-force_key_frames", &times.join(",")]);
+    }
+    cmd.arg(&synth.output);
+    info!("Rendering {} slides to {}", synth.slides, synth.output.display());
+    let status = run_with_log(&mut cmd, |line| trace!("ffmpeg: {}", line))?;
+    if !status.success() {
+        return Err(anyhow!("ffmpeg failed to render the video (exit code: {:?})", status.code()));
+    }
+
+    let truth_path = synth.truth.unwrap_or_else(|| {
+        let stem = synth.output.file_stem().and_then(OsStr::to_str).unwrap_or("output");
+        synth.output.with_file_name(format!("{}_truth.csv", stem))
+    });
+    let mut csv = String::from("time\n");
+    for t in &truth {
+        csv.push_str(&format!("{:.3}\n", t));
+    }
+    fs::write(&truth_path, csv)
+        .with_context(|| format!("Failed to write {}", truth_path.display()))?;
+    info!("Wrote {} slide changes to {}", truth.len(), truth_path.display());
+    Ok(())
+}
+
+/// Small deterministic PRNG for synthetic decks (SplitMix64)
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in `lo..=hi`
+    fn range(&mut self, lo: u32, hi: u32) -> u32 {
+        lo + (self.next() % u64::from(hi - lo + 1)) as u32
+    }
+
+    /// Color with every channel uniform in `lo..=hi`
+    fn color(&mut self, lo: u8, hi: u8) -> image::Rgb<u8> {
+        let mut channel = || self.range(u32::from(lo), u32::from(hi)) as u8;
+        image::Rgb([channel(), channel(), channel()])
+    }
+}
+
+/// Render a slide with a title, bulleted lines of "words" and sometimes a figure.
+///
+/// Words are dark blocks with glyph-like gaps, so slides differ in fine detail
+/// as well as in layout, like real text.
+fn render_synthetic_slide(rng: &mut SplitMix64, width: u32, height: u32) -> RgbImage {
+    let mut img = RgbImage::from_pixel(width, height, rng.color(225, 255));
+    let ink = rng.color(10, 70);
+    let (w, h) = (width as f32, height as f32);
+
+    let fill = |img: &mut RgbImage, x0: u32, y0: u32, x1: u32, y1: u32, color, glyphs: bool| {
+        for y in y0..y1.min(height) {
+            for x in x0..x1.min(width) {
+                if !glyphs || (x - x0) % 5 != 4 {
+                    img.put_pixel(x, y, color);
+                }
+            }
+        }
+    };
+    let words = |img: &mut RgbImage, rng: &mut SplitMix64, x, y, line_h, max_w: u32| {
+        let mut cx = x;
+        let end = x + max_w;
+        while cx < end {
+            let word = rng.range((w * 0.03) as u32, (w * 0.12) as u32);
+            fill(img, cx, y, (cx + word).min(end), y + line_h, ink, true);
+            cx += word + (w * 0.015) as u32;
+        }
+    };
+
+    // Title
+    let title_w = rng.range((w * 0.3) as u32, (w * 0.75) as u32);
+    words(&mut img, rng, (w * 0.08) as u32, (h * 0.08) as u32, (h * 0.07) as u32, title_w);
+
+    // Body lines with bullets, next to an optional figure
+    let figure = rng.range(0, 2) == 0;
+    let text_w = if figure { w * 0.45 } else { w * 0.8 };
+    let lines = rng.range(3, 7);
+    let line_h = (h * 0.04) as u32;
+    for line in 0..lines {
+        let y = (h * 0.25 + line as f32 * h * 0.09) as u32;
+        let x = (w * 0.08) as u32;
+        fill(&mut img, x, y, x + line_h, y + line_h, ink, false);
+        let len = rng.range((text_w * 0.4) as u32, text_w as u32);
+        words(&mut img, rng, x + 2 * line_h, y, line_h, len);
+    }
+    if figure {
+        let color = rng.color(0, 255);
+        let (x0, y0) = ((w * 0.6) as u32, (h * 0.3) as u32);
+        fill(&mut img, x0, y0, (w * 0.92) as u32, (h * 0.8) as u32, color, false);
+    }
+    img
+}
+
+/// A 16x24 arrow cursor: white with a black outline on a transparent background
+fn render_cursor() -> image::RgbaImage {
+    image::RgbaImage::from_fn(16, 24, |x, y| {
+        // Arrow: the triangle x <= y / 1.5 over the first 20 rows
+        let inside = |x: i32, y: i32| {
+            (0..20).contains(&y) && x >= 0 && (x as f32) <= y as f32 / 1.5
+        };
+        let (x, y) = (x as i32, y as i32);
+        let interior = [(0, 0), (-1, 0), (1, 0), (0, -1), (0, 1)]
+            .iter()
+            .all(|&(dx, dy)| inside(x + dx, y + dy));
+        if interior {
+            image::Rgba([255, 255, 255, 255])
+        } else if inside(x, y) {
+            image::Rgba([0, 0, 0, 255])
+        } else {
+            image::Rgba([0, 0, 0, 0])
+        }
+    })
+}
+
+/// Minimum stable durations tried by `--expect-slides` after the configured one
+const EXPECT_MIN_STABLE: [f32; 5] = [0.5, 1.0, 2.0, 3.0, 5.0];
+
+/// Threshold and minimum stable duration producing `target` slides, or the
+/// closest achievable count (with a warning)
+fn fit_expected_slides(config: &Config, frames: &[FrameEntry], target: usize) -> (u32, f32) {
+    let mut candidates = vec![config.min_stable_seconds];
+    candidates.extend(EXPECT_MIN_STABLE.iter().filter(|&&m| m != config.min_stable_seconds));
+
+    let strategy = config.cluster_strategy();
+    match fit_slide_count(frames, strategy.as_ref(), target, &candidates) {
+        Some(fit) if fit.slides == target => {
+            info!(
+                "Found {} slides with --threshold {} --min-stable-seconds {}",
+                target, fit.threshold, fit.min_stable_seconds
+            );
+            (fit.threshold, fit.min_stable_seconds)
+        }
+        Some(fit) => {
+            warn!(
+                "Cannot find exactly {} slides; closest is {} with --threshold {} --min-stable-seconds {}",
+                target, fit.slides, fit.threshold, fit.min_stable_seconds
+            );
+            (fit.threshold, fit.min_stable_seconds)
+        }
+        None => (config.threshold.resolve(frames), config.min_stable_seconds),
+    }
+}
+
+/// Hashed frames of the input, extracted into `temp_dir` or reused from the cache.
+///
+/// Also returns the frame preparation and the directory holding the frames.
+fn load_frames(config: &Config, temp_dir: &Path) -> Result<(Vec<FrameEntry>, FramePrep, PathBuf)> {
+    debug!("Using temporary directory: {}", temp_dir.display());
+    let (frames, prep, frames_dir) = match &config.cache_dir {
+        Some(cache_dir) => cached_analysis(config, cache_dir)?,
+        None => {
+            let (frames, prep) = analyze_video(config, temp_dir)?;
+            (frames, prep, temp_dir.to_path_buf())
+        }
+    };
+    if frames.is_empty() {
+        return Err(anyhow!("No frames extracted. Is the video valid?"));
+    }
+    let frames = if config.refine {
+        refine_boundaries(config, &prep, frames, temp_dir)?
+    } else {
+        frames
+    };
+    info!("Loaded {} frames for processing", frames.len());
+    Ok((frames, prep, frames_dir))
+}
+
+/// Add densely decoded frames around the slide changes found among keyframes.
+///
+/// The frames are clustered once with the configured threshold and minimum
+/// duration (`--expect-slides` is only fitted on the refined frames); the gap
+/// between the last keyframe before and the first keyframe after each cluster
+/// change is decoded at `config.fps`, into a `refine_NNNN` directory of `dir` or
+/// through the pipe with `--pipe`. The returned frames are in time order and
+/// renumbered from 1, ready to be segmented again.
+#[instrument(name = "refine_boundaries", skip(config, prep, frames))]
+fn refine_boundaries(
+    config: &Config,
+    prep: &FramePrep,
+    mut frames: Vec<FrameEntry>,
+    dir: &Path,
+) -> Result<Vec<FrameEntry>> {
+    let threshold = config.threshold.resolve(&frames);
+    let (_, clusters) = cluster_and_merge(config, &frames, threshold, config.min_stable_seconds);
+    let interval = 1.0 / f64::from(config.fps);
+    let gaps: Vec<(f64, f64)> = clusters
+        .iter()
+        .skip(1)
+        .filter_map(|cluster| {
+            let first = *cluster.first()?;
+            let before = frames.get(first.checked_sub(1)?)?;
+            Some((before.pts, frames[first].pts))
+        })
+        .filter(|(start, end)| end - start > interval)
+        .collect();
+    info!("Refining {} slide changes with a dense decode", gaps.len());
+
+    let refined = gaps
+        .par_iter()
+        .enumerate()
+        .map(|(k, &(start, end))| -> Result<Vec<FrameEntry>> {
+            let sub = dir.join(format!("refine_{:04}", k + 1));
+            let inner = if config.pipe {
+                hash_piped(config, prep, Some((start, end)), &sub)?
+            } else {
+                let timestamps = extract_frames(config, &sub, Some((start, end)))?;
+                if timestamps.is_empty() {
+                    return Ok(Vec::new());
+                }
+                let files = list_frame_files(&sub)?;
+                load_frame_hashes(config, &files, &timestamps, prep)?
+            };
+            // The keyframes at both ends are already there
+            let eps = interval / 2.0;
+            Ok(inner.into_iter().filter(|f| f.pts > start + eps && f.pts < end - eps).collect())
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    frames.extend(refined.into_iter().flatten());
+    frames.sort_by(|a, b| a.pts.total_cmp(&b.pts));
+    for (i, frame) in frames.iter_mut().enumerate() {
+        frame.idx = i + 1;
+    }
+    Ok(frames)
+}
+
+/// `tune` subcommand: slide counts over a grid of thresholds and minimum stable durations
+#[instrument(name = "tune", skip(tune))]
+fn tune_settings(tune: TuneArgs) -> Result<()> {
+    if tune.thresholds.is_empty() || tune.min_stable.is_empty() {
+        return Err(anyhow!("At least one threshold and one min-stable duration are required"));
+    }
+    if let Some(t) = tune.thresholds.iter().find(|&&t| t > 64) {
+        return Err(anyhow!("Threshold must be 0..=64, got: {}", t));
+    }
+    if let Some(m) = tune.min_stable.iter().find(|&&m| m < 0.0) {
+        return Err(anyhow!("min_stable_seconds must be non-negative, got: {}", m));
+    }
+
+    let config = Config::from_args(tune.args)?;
+    let temp_dir = TempDir::new().context("Failed to create temporary directory for frames")?;
+    let (frames, _, _) = load_frames(&config, temp_dir.path())?;
+
+    if let Some(t) = estimate_threshold(&frames) {
+        info!("Estimated threshold for --threshold auto: {}", t);
+    }
+    let strategy = config.cluster_strategy();
+    let counts: Vec<Vec<usize>> = tune
+        .thresholds
+        .par_iter()
+        .map(|&threshold| {
+            let initial = strategy.cluster(&frames, threshold);
+            tune.min_stable
+                .iter()
+                .map(|&min_stable| {
+                    let mut clusters = initial.clone();
+                    merge_short_clusters(&mut clusters, &frames, min_stable, threshold);
+                    clusters.len()
+                })
+                .collect()
+        })
+        .collect();
+
+    println!("Slides per --threshold (rows) and --min-stable-seconds (columns):");
+    let header: Vec<String> = tune.min_stable.iter().map(|m| format!("{:>8}", format!("{}s", m))).collect();
+    println!("{:>9} {}", "threshold", header.join(""));
+    for (threshold, row) in tune.thresholds.iter().zip(&counts) {
+        let cells: Vec<String> = row.iter().map(|c| format!("{:>8}", c)).collect();
+        println!("{:>9} {}", threshold, cells.join(""));
+    }
+
+    let rows = || {
+        tune.thresholds.iter().zip(&counts).flat_map(|(t, row)| {
+            tune.min_stable.iter().zip(row).map(move |(m, c)| (*t, *m, *c))
+        })
+    };
+    if let Some(path) = &tune.csv {
+        let mut csv = String::from("threshold,min_stable_seconds,slides\n");
+        for (t, m, c) in rows() {
+            csv.push_str(&format!("{},{},{}\n", t, m, c));
+        }
+        fs::write(path, csv).with_context(|| format!("Failed to write {}", path.display()))?;
+        info!("Wrote tuning table to {}", path.display());
+    }
+    if let Some(path) = &tune.json {
+        let entries: Vec<String> = rows()
+            .map(|(t, m, c)| {
+                format!("  {{\"threshold\": {}, \"min_stable_seconds\": {}, \"slides\": {}}}", t, m, c)
+            })
+            .collect();
+        let json = format!("[\n{}\n]\n", entries.join(",\n"));
+        fs::write(path, json).with_context(|| format!("Failed to write {}", path.display()))?;
+        info!("Wrote tuning table to {}", path.display());
+    }
+    Ok(())
+}
+
+/// Extract frames into `dir`, locate the screen, bars and dynamic regions, and hash the frames
+fn analyze_video(config: &Config, dir: &Path) -> Result<(Vec<FrameEntry>, FramePrep)> {
+    let intervals = match config.detector {
+        Detector::Phash if config.pipe => return analyze_piped(config, dir),
+        Detector::Phash => None,
+        Detector::Scene => Some(detect_scenes(config)?),
+        Detector::Freeze => Some(detect_freezes(config)?),
+    };
+    let timestamps = match &intervals {
+        None => extract_frames(config, dir, None)?,
+        Some(intervals) => extract_interval_frames(config, dir, intervals)?,
+    };
+    let files = list_frame_files(dir)?;
+    let prep = prepare_frames(config, &files)?;
+    let mut frames = load_frame_hashes(config, &files, &timestamps, &prep)?;
+    // An interval frame is shown until its interval ends, not until the next one starts
+    if let Some(intervals) = &intervals {
+        for frame in &mut frames {
+            frame.end = frame.idx.checked_sub(1).and_then(|n| intervals.get(n)).map(|iv| iv.1);
+        }
+    }
+    Ok((frames, prep))
+}
+
+/// Name of the index file of a cache entry (no extension, so it is never taken for a frame)
+const CACHE_INDEX: &str = "index";
+/// First line of a cache index, bumped whenever its layout changes
+const CACHE_HEADER: &str = "slidesplit-cache 2";
+
+/// [`analyze_video`] through the cache in `cache_dir`.
+///
+/// Each entry is a directory named after [`cache_key`], holding the extracted
+/// frames and an index with their timestamps, hashes and the frame preparation.
+/// Returns the frames, their preparation and the directory holding the frames.
+#[instrument(name = "cache", skip(config))]
+fn cached_analysis(
+    config: &Config,
+    cache_dir: &Path,
+) -> Result<(Vec<FrameEntry>, FramePrep, PathBuf)> {
+    let key = cache_key(config)?;
+    let entry = cache_dir.join(&key);
+    let index = entry.join(CACHE_INDEX);
+
+    if index.is_file() {
+        match read_cache(&entry, &config.hash_weights, config.fusion.into()) {
+            Ok((frames, prep)) => {
+                info!("Loaded {} frames from cache {}", frames.len(), entry.display());
+                if let (true, Some(first)) = (config.auto_mask, frames.first()) {
+                    // The mask is written while analyzing, which a cache hit skips
+                    let (width, height) = match prep.screen {
+                        Some(quad) => quad.size(),
+                        None => image::image_dimensions(&first.path).with_context(|| {
+                            format!("Failed to read frame size: {}", first.path.display())
+                        })?,
+                    };
+                    save_mask(config, &prep.mask, width, height)?;
+                }
+                return Ok((frames, prep, entry));
+            }
+            Err(e) => warn!("Ignoring unreadable cache {}: {:#}", index.display(), e),
+        }
+    } else {
+        debug!("No cache entry for key {}", key);
+    }
+
+    // Start from an empty entry: a previous run may have stopped half-way
+    if entry.exists() {
+        fs::remove_dir_all(&entry)
+            .with_context(|| format!("Failed to clear cache entry: {}", entry.display()))?;
+    }
+    fs::create_dir_all(&entry)
+        .with_context(|| format!("Failed to create cache directory: {}", entry.display()))?;
+
+    let (frames, prep) = analyze_video(config, &entry)?;
+    write_cache(&entry, &frames, &prep)?;
+    info!("Cached {} frames in {}", frames.len(), entry.display());
+    Ok((frames, prep, entry))
+}
+
+/// Bytes of the input hashed per read when computing the cache key
+const CACHE_KEY_READ_SIZE: usize = 1 << 20;
+
+/// Cache key: a hash of the full input file contents and of every setting that
+/// affects extracted frames or their hashes
+fn cache_key(config: &Config) -> Result<String> {
+    debug!("Hashing input contents for the cache key");
+    let read_err = || format!("Failed to read input: {}", config.input.display());
+    let mut file = fs::File::open(&config.input)
+        .with_context(|| format!("Failed to open input: {}", config.input.display()))?;
+    let mut content = Fnv1a::default();
+    let mut buf = Vec::with_capacity(CACHE_KEY_READ_SIZE);
+    loop {
+        buf.clear();
+        (&mut file)
+            .take(CACHE_KEY_READ_SIZE as u64)
+            .read_to_end(&mut buf)
+            .with_context(read_err)?;
+        content.write_words(&buf);
+        if buf.len() < CACHE_KEY_READ_SIZE {
+            break;
+        }
+    }
+
+    let settings = format!(
+        "detector={:?} scene_threshold={} freeze_noise={} freeze_duration={} fps={} \
+         keyframes_only={} format={} webp_lossless={} hashes={:?} include={:?} exclude={:?} \
+         auto_mask={} autocrop={} rectify={} corners={:?}",
+        config.detector,
+        config.scene_threshold,
+        config.freeze_noise,
+        config.freeze_duration,
+        config.fps,
+        config.keyframes_only,
+        config.format.ext(),
+        config.webp_lossless,
+        config.hashes,
+        config.mask.include,
+        config.mask.exclude,
+        config.auto_mask,
+        config.autocrop,
+        config.rectify,
+        config.corners,
+    );
+    let mut key = Fnv1a::default();
+    key.write(settings.as_bytes());
+    Ok(format!("{:016x}{:016x}", content.0, key.0))
+}
+
+/// 64-bit FNV-1a, a simple hash that is stable across builds and platforms
+struct Fnv1a(u64);
+
+impl Default for Fnv1a {
+    fn default() -> Self {
+        Fnv1a(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv1a {
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    /// Like [`Fnv1a::write`] but mixes in eight bytes per step, which keeps hashing
+    /// a long recording fast; the result differs from writing the same bytes
+    fn write_words(&mut self, bytes: &[u8]) {
+        let mut words = bytes.chunks_exact(8);
+        for word in &mut words {
+            let word = u64::from_le_bytes(word.try_into().expect("chunk of 8 bytes"));
+            self.0 = (self.0 ^ word).wrapping_mul(0x0100_0000_01b3);
+        }
+        self.write(words.remainder());
+    }
+}
+
+/// Write the index of a cache entry: frame preparation, then one line per frame
+/// (number, timestamp, end time or `-`, file name, base64 hashes)
+fn write_cache(entry: &Path, frames: &[FrameEntry], prep: &FramePrep) -> Result<()> {
+    let mut lines = vec![CACHE_HEADER.to_string()];
+    if let Some(quad) = prep.screen {
+        let corners: Vec<String> =
+            quad.corners.iter().map(|(x, y)| format!("{} {}", x, y)).collect();
+        lines.push(format!("screen {}", corners.join(" ")));
+    }
+    if let Some((x, y, w, h)) = prep.content {
+        lines.push(format!("content {} {} {} {}", x, y, w, h));
+    }
+    lines.extend(prep.mask.include.iter().map(|r| format!("include {}", r)));
+    lines.extend(prep.mask.exclude.iter().map(|r| format!("exclude {}", r)));
+    for frame in frames {
+        let name = frame
+            .path
+            .file_name()
+            .and_then(OsStr::to_str)
+            .ok_or_else(|| anyhow!("Frame path has no UTF-8 file name: {}", frame.path.display()))?;
+        let end = frame.end.map_or("-".to_string(), |end| end.to_string());
+        let hash = frame.hash.to_base64();
+        let mut line = format!("frame {} {} {} {} {}", frame.idx, frame.pts, end, name, hash);
+        for hash in frame.multi.iter().flat_map(|m| &m.extra) {
+            line.push(' ');
+            line.push_str(&hash.to_base64());
+        }
+        lines.push(line);
+    }
+
+    // Write the index last and atomically: its presence marks a complete entry
+    let partial = entry.join(format!("{}.partial", CACHE_INDEX));
+    fs::write(&partial, lines.join("\n") + "\n")
+        .with_context(|| format!("Failed to write cache index: {}", partial.display()))?;
+    let index = entry.join(CACHE_INDEX);
+    fs::rename(&partial, &index)
+        .with_context(|| format!("Failed to write cache index: {}", index.display()))?;
+    Ok(())
+}
+
+/// Read the frames and frame preparation of a cache entry written by [`write_cache`].
+///
+/// Frames must carry one hash per weight in `weights`, fused by `fusion`.
+fn read_cache(
+    entry: &Path,
+    weights: &Arc<[f32]>,
+    fusion: HashFusion,
+) -> Result<(Vec<FrameEntry>, FramePrep)> {
+    let index = entry.join(CACHE_INDEX);
+    let text = fs::read_to_string(&index)
+        .with_context(|| format!("Failed to read cache index: {}", index.display()))?;
+    let mut lines = text.lines();
+    if lines.next() != Some(CACHE_HEADER) {
+        return Err(anyhow!("Unknown cache format"));
+    }
+
+    let mut prep = FramePrep::default();
+    let mut frames = Vec::new();
+    for (n, line) in lines.enumerate() {
+        let bad = || anyhow!("Malformed cache line {}: {}", n + 2, line);
+        let mut fields = line.split(' ');
+        match fields.next() {
+            Some("screen") => {
+                let v: Vec<f32> =
+                    fields.map(str::parse).collect::<Result<_, _>>().map_err(|_| bad())?;
+                let [x1, y1, x2, y2, x3, y3, x4, y4] = v[..] else {
+                    return Err(bad());
+                };
+                prep.screen = Some(Quad { corners: [(x1, y1), (x2, y2), (x3, y3), (x4, y4)] });
+            }
+            Some("content") => {
+                let v: Vec<u32> =
+                    fields.map(str::parse).collect::<Result<_, _>>().map_err(|_| bad())?;
+                let [x, y, w, h] = v[..] else {
+                    return Err(bad());
+                };
+                prep.content = Some((x, y, w, h));
+            }
+            Some(kind @ ("include" | "exclude")) => {
+                let rect: Rect = fields.next().ok_or_else(bad)?.parse().map_err(|_| bad())?;
+                match kind {
+                    "include" => prep.mask.include.push(rect),
+                    _ => prep.mask.exclude.push(rect),
+                }
+            }
+            Some("frame") => {
+                let idx: usize = fields.next().and_then(|f| f.parse().ok()).ok_or_else(bad)?;
+                let pts: f64 = fields.next().and_then(|f| f.parse().ok()).ok_or_else(bad)?;
+                let end = match fields.next().ok_or_else(bad)? {
+                    "-" => None,
+                    end => Some(end.parse::<f64>().map_err(|_| bad())?),
+                };
+                let path = entry.join(fields.next().ok_or_else(bad)?);
+                let mut hashes = fields.map(ImageHash::from_base64);
+                let hash = hashes.next().ok_or_else(bad)?.map_err(|_| bad())?;
+                let extra = hashes.collect::<Result<Vec<_>, _>>().map_err(|_| bad())?;
+                if extra.len() + 1 != weights.len() {
+                    return Err(bad());
+                }
+                if !path.is_file() {
+                    return Err(anyhow!("Cached frame is missing: {}", path.display()));
+                }
+                let multi = (!extra.is_empty()).then(|| MultiHash {
+                    extra,
+                    weights: Arc::clone(weights),
+                    fusion,
+                });
+                frames.push(FrameEntry::new(idx, pts, path, hash).with_end(end).with_multi(multi));
+            }
+            _ => return Err(bad()),
+        }
+    }
+    Ok((frames, prep))
+}
+
+/// Merge slides shown more than once, comparing the final state of each slide
+fn dedupe_slides(
+    clusters: &[Vec<usize>],
+    slides: &[Vec<usize>],
+    frames: &[FrameEntry],
+    threshold: u32,
+) -> Vec<Vec<usize>> {
+    let finals: Vec<Vec<usize>> = slides
+        .iter()
+        .map(|s| clusters[*s.last().unwrap()].clone())
+        .collect();
+    let groups = dedupe_clusters(&finals, frames, threshold);
+    info!("Deduplication found {} unique slides in {} slides", groups.len(), slides.len());
+
+    groups
+        .iter()
+        .map(|g| g.iter().flat_map(|&s| slides[s].iter().copied()).collect())
+        .collect()
+}
+
+/// Split a slide's clusters into the separate times it was on screen.
+///
+/// A slide lists ascending cluster indices; each run of consecutive clusters is
+/// one occurrence, made of its build steps in order.
+fn occurrences(slide: &[usize]) -> impl Iterator<Item = &[usize]> {
+    slide.chunk_by(|a, b| *b == a + 1)
+}
+
+/// Write one representative frame per slide to output directory.
+///
+/// The representatives are taken from the last (fully built) cluster of the
+/// slide's occurrence whose last cluster is longest, following `--representative`,
+/// or fused from all its frames with `--fuse-frames`; with `--builds steps` every
+/// step of that occurrence is written.
+#[instrument(name = "write_output", skip(config, prep, clusters, slides, frames))]
+fn write_output_slides(
+    config: &Config,
+    prep: &FramePrep,
+    clusters: &[Vec<usize>],
+    slides: &[Vec<usize>],
+    frames: &[FrameEntry],
+    threshold: u32,
+) -> Result<usize> {
     let ext = config.format.ext();
     debug!("Writing output slides in format: {}", ext);
 
-    let wrote: usize = clusters
+    let wrote: usize = slides
         .par_iter()
         .enumerate()
-        .map(|(slide_num, cluster)| -> Result<usize> {
-            if cluster.is_empty() {
+        .map(|(slide_num, slide)| -> Result<usize> {
+            let longest = occurrences(slide)
+                .max_by_key(|o| o.last().map_or(0, |&c| clusters[c].len()))
+                .unwrap_or(&[]);
+            let write_steps = config.builds == Builds::Steps && longest.len() > 1;
+            let outputs: Vec<(String, &Vec<usize>)> = if write_steps {
+                longest
+                    .iter()
+                    .enumerate()
+                    .map(|(step, &c)| {
+                        (format!("slide_{:02}_step_{:02}", slide_num, step + 1), &clusters[c])
+                    })
+                    .collect()
+            } else {
+                longest
+                    .last()
+                    .map(|&c| (format!("slide_{:02}", slide_num), &clusters[c]))
+                    .into_iter()
+                    .collect()
+            };
+            if outputs.iter().all(|(_, cluster)| cluster.is_empty()) {
                 debug!("Skipping empty cluster {}", slide_num);
                 return Ok(0);
             }
-            
-            // Use median frame of cluster as representative
-            let rep = &frames[cluster[cluster.len() / 2]];
-            let out_name = format!("slide_{:02}.{}", slide_num, ext);
-            let out_path = config.out_dir.join(&out_name);
-            
-            debug!("Writing slide {} from frame {} to {}", slide_num, rep.idx, out_name);
-            
-            fs::copy(&rep.path, &out_path).with_context(|| {
-                format!(
-                    "Failed to copy representative frame {} -> {}",
-                    rep.path.display(),
-                    out_path.display()
-                )
-            })?;
+
+            for (name, cluster) in outputs.into_iter().filter(|(_, c)| !c.is_empty()) {
+                if let Some(mode) = config.fuse_frames {
+                    let out_path = config.out_dir.join(format!("{}.{}", name, ext));
+                    debug!("Fusing {} frames of slide {} into {}", cluster.len(), slide_num, out_path.display());
+                    write_fused_slide(config, prep, cluster, frames, mode.into(), &out_path)?;
+                    continue;
+                }
+
+                let sharpness = if config.representative == Representative::Sharpest {
+                    load_sharpness(cluster, frames)?
+                } else {
+                    Vec::new()
+                };
+                let reps = select_representatives(
+                    cluster,
+                    frames,
+                    config.representative.into(),
+                    config.representatives,
+                    threshold,
+                    &sharpness,
+                );
+                ensure_frame_files(config, frames, &reps)?;
+
+                for (k, &f) in reps.iter().enumerate() {
+                    let rep = &frames[f];
+                    let out_name = if config.representatives > 1 {
+                        format!("{}_rep_{:02}.{}", name, k + 1, ext)
+                    } else {
+                        format!("{}.{}", name, ext)
+                    };
+                    let out_path = config.out_dir.join(&out_name);
+
+                    debug!("Writing slide {} from frame {} to {}", slide_num, rep.idx, out_name);
+
+                    if prep.transforms_output(config) {
+                        let img = image::open(&rep.path).with_context(|| {
+                            format!("Failed to open frame image: {}", rep.path.display())
+                        })?;
+                        write_image(config, prep, img.to_rgb8(), &out_path)?;
+                        continue;
+                    }
+                    fs::copy(&rep.path, &out_path).with_context(|| {
+                        format!(
+                            "Failed to copy representative frame {} -> {}",
+                            rep.path.display(),
+                            out_path.display()
+                        )
+                    })?;
+                }
+            }
             Ok(1)
         })
         .try_reduce(|| 0, |a, b| Ok(a + b))?;
 
-    if wrote == 0 {
-        return Err(anyhow!(
-            "No slides detected (threshold too strict?). Try lowering --threshold or increasing --fps."
-        ));
+    if wrote == 0 {
+        return Err(anyhow!(
+            "No slides detected (threshold too strict?). Try lowering --threshold or increasing --fps."
+        ));
+    }
+
+    Ok(wrote)
+}
+
+/// Maximum number of frames of a cluster decoded for `--fuse-frames`
+const MAX_FUSED_FRAMES: usize = 25;
+
+/// Fuse the frames of a cluster (evenly subsampled) and encode the result to `out_path`
+fn write_fused_slide(
+    config: &Config,
+    prep: &FramePrep,
+    cluster: &[usize],
+    frames: &[FrameEntry],
+    mode: FrameFusion,
+    out_path: &Path,
+) -> Result<()> {
+    let step = cluster.len().div_ceil(MAX_FUSED_FRAMES).max(1);
+    let fused: Vec<usize> = cluster.iter().copied().step_by(step).collect();
+    let images = fused
+        .iter()
+        .map(|&f| -> Result<RgbImage> {
+            let path = &frames[f].path;
+            let img = image::open(path)
+                .with_context(|| format!("Failed to open frame image: {}", path.display()))?;
+            Ok(img.to_rgb8())
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let fused = fuse_frames(&images, mode)
+        .ok_or_else(|| anyhow!("Cannot fuse frames of different sizes into {}", out_path.display()))?;
+    write_image(config, prep, fused, out_path)
+}
+
+/// Write a decoded slide image in the output format: rectified, cropped to the
+/// content area and, with `--crop-output`, to the include regions
+fn write_image(config: &Config, prep: &FramePrep, img: RgbImage, out_path: &Path) -> Result<()> {
+    let mut img = prep.rectified(img);
+    if let Some((x, y, w, h)) = prep.output_crop(config, img.width(), img.height()) {
+        img = image::imageops::crop_imm(&img, x, y, w.max(1), h.max(1)).to_image();
+    }
+
+    let tmp = TempDir::new().context("Failed to create temporary directory for slide image")?;
+    let src = tmp.path().join("slide.png");
+    img.save(&src)
+        .with_context(|| format!("Failed to write slide image: {}", src.display()))?;
+    encode_image(config, &src, out_path)
+}
+
+/// Re-encode a single image with ffmpeg, using the encoder options of the output format
+fn encode_image(config: &Config, src: &Path, dst: &Path) -> Result<()> {
+    let mut cmd = Command::new(&config.ffmpeg_bin);
+    cmd.args(["-hide_banner", "-nostats", "-loglevel", "level+info", "-y", "-i"])
+        .arg(src)
+        .args(["-frames:v", "1", "-update", "1"])
+        .args(config.format.encoder_args(config.webp_lossless))
+        .arg(dst);
+
+    let status = run_with_log(&mut cmd, |line| trace!("ffmpeg: {}", line))?;
+    if !status.success() {
+        return Err(anyhow!(
+            "ffmpeg failed to encode {} (exit code: {:?})",
+            dst.display(),
+            status.code()
+        ));
+    }
+    Ok(())
+}
+
+/// Record every time range each slide was on screen in `slides.csv`
+#[instrument(name = "write_ranges", skip(config, clusters, slides, frames))]
+fn write_slide_ranges(
+    config: &Config,
+    clusters: &[Vec<usize>],
+    slides: &[Vec<usize>],
+    frames: &[FrameEntry],
+) -> Result<()> {
+    let mut csv = String::from("slide,start_seconds,end_seconds\n");
+    for (slide_num, slide) in slides.iter().enumerate() {
+        for occurrence in occurrences(slide) {
+            let (start, _) = cluster_span(&clusters[occurrence[0]], frames);
+            let (_, end) = cluster_span(&clusters[*occurrence.last().unwrap()], frames);
+            csv.push_str(&format!("{},{:.3},{:.3}\n", slide_num, start, end));
+        }
+    }
+
+    let path = config.out_dir.join("slides.csv");
+    fs::write(&path, csv).with_context(|| format!("Failed to write slide ranges: {}", path.display()))?;
+    info!("Wrote slide time ranges to {}", path.display());
+    Ok(())
+}
+
+/// Record when each build step of each slide appeared in `builds.csv`
+#[instrument(name = "write_builds", skip(config, clusters, slides, frames))]
+fn write_build_timings(
+    config: &Config,
+    clusters: &[Vec<usize>],
+    slides: &[Vec<usize>],
+    frames: &[FrameEntry],
+) -> Result<()> {
+    let mut csv = String::from("slide,step,start_seconds,end_seconds\n");
+    for (slide_num, slide) in slides.iter().enumerate() {
+        for occurrence in occurrences(slide) {
+            for (step, &c) in occurrence.iter().enumerate() {
+                let (start, end) = cluster_span(&clusters[c], frames);
+                csv.push_str(&format!("{},{},{:.3},{:.3}\n", slide_num, step + 1, start, end));
+            }
+        }
+    }
+
+    let path = config.out_dir.join("builds.csv");
+    fs::write(&path, csv).with_context(|| format!("Failed to write build timings: {}", path.display()))?;
+    info!("Wrote build step timings to {}", path.display());
+    Ok(())
+}
+
+/// Slides whose confidence falls below this are listed for manual review
+const LOW_CONFIDENCE: f32 = 0.5;
+
+/// Write per-cluster diagnostics to `diagnostics.csv` and log low-confidence slides.
+///
+/// A slide's confidence is the lowest confidence of its clusters.
+#[instrument(name = "write_diagnostics", skip_all)]
+fn write_diagnostics(
+    config: &Config,
+    initial: &[Vec<usize>],
+    clusters: &[Vec<usize>],
+    slides: &[Vec<usize>],
+    frames: &[FrameEntry],
+    threshold: u32,
+) -> Result<()> {
+    let diags = diagnose_clusters(initial, clusters, frames, threshold);
+    let opt = |d: Option<u32>| d.map(|d| d.to_string()).unwrap_or_default();
+
+    let mut csv = String::from(
+        "slide,start_seconds,end_seconds,frames,spread,boundary_prev,boundary_next,merged_from,confidence\n",
+    );
+    let mut low = Vec::new();
+    for (slide_num, slide) in slides.iter().enumerate() {
+        let mut slide_confidence = 1.0f32;
+        for &c in slide.iter().filter(|&&c| !clusters[c].is_empty()) {
+            let d = &diags[c];
+            let (start, end) = cluster_span(&clusters[c], frames);
+            debug!("Slide {} cluster {}: {:?}", slide_num, c, d);
+            csv.push_str(&format!(
+                "{},{:.3},{:.3},{},{:.2},{},{},{},{:.3}\n",
+                slide_num,
+                start,
+                end,
+                clusters[c].len(),
+                d.spread,
+                opt(d.boundary_prev),
+                opt(d.boundary_next),
+                d.merged_from,
+                d.confidence
+            ));
+            slide_confidence = slide_confidence.min(d.confidence);
+        }
+        if slide_confidence < LOW_CONFIDENCE {
+            low.push(format!("slide_{:02} ({:.2})", slide_num, slide_confidence));
+        }
     }
 
-    Ok(wrote)
+    let path = config.out_dir.join("diagnostics.csv");
+    fs::write(&path, csv).with_context(|| format!("Failed to write diagnostics: {}", path.display()))?;
+    info!("Wrote slide diagnostics to {}", path.display());
+    if low.is_empty() {
+        info!("All slides have confidence >= {}", LOW_CONFIDENCE);
+    } else {
+        warn!("{} low-confidence slide(s) to review: {}", low.len(), low.join(", "));
+    }
+    Ok(())
 }
 
-/// Keep temporary frames in output directory if requested
+/// Keep temporary frames in output directory if requested, with the frames
+/// decoded by `--refine` in their `refine_NNNN` subdirectories
 #[instrument(name = "keep_temps", skip(config))]
-fn keep_temporary_frames(config: &Config, frames_dir: &Path) -> Result<()> {
+fn keep_temporary_frames(config: &Config, frames_dir: &Path, temp_dir: &Path) -> Result<()> {
     let keep_path = config.out_dir.join("frames_raw");
     info!("Keeping temporary frames in: {}", keep_path.display());
-    
-    fs::create_dir_all(&keep_path)
-        .with_context(|| format!("Failed to create frames_raw directory: {}", keep_path.display()))?;
+
+    let mut copied = copy_frame_files(frames_dir, &keep_path)?;
+    for entry in WalkDir::new(temp_dir)
+        .min_depth(1)
+        .max_depth(1)
+        .into_iter()
+        .filter_map(|e| e.ok())
+    {
+        let refined = entry.file_name().to_string_lossy().starts_with("refine_");
+        if refined && entry.file_type().is_dir() {
+            copied += copy_frame_files(entry.path(), &keep_path.join(entry.file_name()))?;
+        }
+    }
+
+    debug!("Copied {} temporary frames to frames_raw", copied);
+    Ok(())
+}
+
+/// Copy the frame files directly in `from` into `to`, returning how many were copied
+fn copy_frame_files(from: &Path, to: &Path) -> Result<usize> {
+    fs::create_dir_all(to)
+        .with_context(|| format!("Failed to create frames_raw directory: {}", to.display()))?;
     
     let mut copied = 0;
-    for entry in WalkDir::new(frames_dir)
+    for entry in WalkDir::new(from)
         .min_depth(1)
         .max_depth(1)
         .into_iter()
@@ -300,7 +2086,7 @@ fn keep_temporary_frames(config: &Config, frames_dir: &Path) -> Result<()> {
         let source_path = entry.path();
         if source_path.is_file() && source_path.extension().is_some() {
             if let Some(filename) = source_path.file_name() {
-                let dest_path = keep_path.join(filename);
+                let dest_path = to.join(filename);
                 fs::copy(source_path, &dest_path)
                     .with_context(|| format!("Failed to copy temp frame: {} -> {}", 
                                             source_path.display(), dest_path.display()))?;
@@ -308,9 +2094,7 @@ fn keep_temporary_frames(config: &Config, frames_dir: &Path) -> Result<()> {
             }
         }
     }
-    
-    debug!("Copied {} temporary frames to frames_raw", copied);
-    Ok(())
+    Ok(copied)
 }
 
 fn default_out_dir(input: &Path) -> PathBuf {
@@ -352,21 +2136,57 @@ fn ensure_ffmpeg_available() -> Result<PathBuf> {
     }
 }
 
-#[instrument(name = "run_command", skip(cmd))]
-fn run_and_stream(cmd: &mut Command) -> Result<std::process::ExitStatus> {
-    // Log the command being executed (at debug level to avoid leaking sensitive paths)
+/// Run an ffmpeg command started with `-loglevel level+info`, routing its log.
+///
+/// stdout is inherited. Each stderr line carries its level tag: errors and
+/// warnings are re-emitted through tracing, every other line is handed to
+/// `on_info` (e.g. to parse filter output).
+#[instrument(name = "run_command", skip(cmd, on_info))]
+fn run_with_log(
+    cmd: &mut Command,
+    mut on_info: impl FnMut(&str),
+) -> Result<std::process::ExitStatus> {
     debug!("Executing command: {}", format_command(cmd));
-    
-    // Inherit parent's stdout/stderr so the child output is streamed directly
-    // to the console in real time without buffering here.
+
     cmd.stdout(Stdio::inherit());
-    cmd.stderr(Stdio::inherit());
+    cmd.stderr(Stdio::piped());
 
     let mut child = cmd.spawn().context("Failed to spawn command")?;
+    let stderr = child.stderr.take().context("Failed to capture command stderr")?;
+    route_log(stderr, &mut on_info)?;
     let status = child.wait().context("Failed waiting for command")?;
     Ok(status)
 }
 
+/// Route the lines of an ffmpeg log as described in [`run_with_log`]
+fn route_log(stderr: impl Read, on_info: &mut impl FnMut(&str)) -> Result<()> {
+    for line in BufReader::new(stderr).lines() {
+        let line = line.context("Failed reading command output")?;
+        if ["[error]", "[fatal]", "[panic]"].iter().any(|tag| line.contains(tag)) {
+            error!("ffmpeg: {}", line);
+        } else if line.contains("[warning]") {
+            warn!("ffmpeg: {}", line);
+        } else {
+            on_info(&line);
+        }
+    }
+    Ok(())
+}
+
+/// Parse the output frame number and presentation time from an ffmpeg `showinfo` line
+fn parse_showinfo(line: &str) -> Option<(usize, f64)> {
+    if !line.contains("Parsed_showinfo") {
+        return None;
+    }
+    let field = |key: &str| -> Option<&str> {
+        let rest = &line[line.find(key)? + key.len()..];
+        rest.split_whitespace().next()
+    };
+    let n = field(" n:")?.parse().ok()?;
+    let pts_time = field("pts_time:")?.parse().ok()?;
+    Some((n, pts_time))
+}
+
 /// Format a command for logging (hide sensitive path details)
 fn format_command(cmd: &Command) -> String {
     format!("{:?}", cmd)
@@ -376,9 +2196,21 @@ fn format_command(cmd: &Command) -> String {
         + "..."
 }
 
+/// Extract sampled frames as `frame_%06d.<ext>` (numbered from 1) into `outdir`.
+///
+/// Returns the presentation timestamp in seconds of each written frame, indexed
+/// by frame number - 1, as reported by ffmpeg's `showinfo` filter. Entries are
+/// NaN if ffmpeg did not report a frame.
+///
+/// With a `(start, end)` range only that part of the video is decoded, always
+/// at `config.fps`; timestamps still count from the start of the video.
 #[instrument(name = "extract_frames", skip(config))]
-fn extract_frames(config: &Config, outdir: &Path) -> Result<()> {
-    info!("Extracting frames at {} fps to {}", config.fps, outdir.display());
+fn extract_frames(config: &Config, outdir: &Path, range: Option<(f64, f64)>) -> Result<Vec<f64>> {
+    match range {
+        Some((start, end)) => debug!("Extracting frames from {:.3}s to {:.3}s", start, end),
+        None if config.keyframes_only => info!("Extracting keyframes to {}", outdir.display()),
+        None => info!("Extracting frames at {} fps to {}", config.fps, outdir.display()),
+    }
     
     fs::create_dir_all(outdir)
         .with_context(|| format!("Failed to create frames directory: {}", outdir.display()))?;
@@ -390,58 +2222,439 @@ fn extract_frames(config: &Config, outdir: &Path) -> Result<()> {
         .ok_or_else(|| anyhow!("Output pattern contains invalid UTF-8: {}", pattern.display()))?;
 
     let mut cmd = Command::new(&config.ffmpeg_bin);
-    cmd.args([
-        "-hide_banner",
-        "-loglevel",
-        "error",
-        "-i",
-        input_str,
-        "-vf",
-        &format!("fps={}", config.fps),
-        "-vsync",
-        "vfr",
-    ]);
-
-    // Format-specific lossless flags (encoder opts)
-    match config.format {
-        OutFormat::Webp if config.webp_lossless => {
-            debug!("Using WebP lossless encoding");
-            cmd.args(["-lossless", "1"]);
+    cmd.args(["-hide_banner", "-nostats", "-loglevel", "level+info"]);
+    let (filter, offset) = match range {
+        Some((start, end)) => {
+            (format!("{},showinfo", config.fps_select()), seek_range(&mut cmd, start, end))
         }
-        OutFormat::Tiff => {
-            debug!("Using TIFF with LZW compression");
-            cmd.args(["-compression_algo", "lzw"]);
+        None => {
+            cmd.args(config.decode_args());
+            (config.sample_filter("showinfo"), 0.0)
         }
-        OutFormat::Bmp => {
-            debug!("Using BMP format (inherently lossless)");
-        }
-        OutFormat::Png => {
-            debug!("Using PNG with high compression");
-            cmd.args(["-compression_level", "12"]);
-        }
-        OutFormat::Jpg | OutFormat::Jpeg => {
-            debug!("Using JPEG with high quality (not lossless)");
-            cmd.args(["-qscale:v", "2"]);
-        }
-        _ => {}
-    }
+    };
+    cmd.args(["-i", input_str, "-vf", &filter, "-vsync", "vfr"]);
+
+    // Format-specific lossless flags (encoder opts)
+    let encoder_args = config.format.encoder_args(config.webp_lossless);
+    debug!("Using {} encoder options: {:?}", config.format.ext(), encoder_args);
+    cmd.args(encoder_args);
 
     cmd.arg(pattern_str);
 
     debug!("Starting frame extraction");
-    let status = run_and_stream(&mut cmd)?;
+    let mut timestamps: Vec<f64> = Vec::new();
+    let status = run_with_log(&mut cmd, |line| match parse_showinfo(line) {
+        Some((n, pts_time)) => {
+            if timestamps.len() <= n {
+                timestamps.resize(n + 1, f64::NAN);
+            }
+            // Input seeking restarts the timestamps at the seek point
+            timestamps[n] = offset + pts_time;
+        }
+        None => trace!("ffmpeg: {}", line),
+    })?;
     
     if !status.success() {
         return Err(anyhow!("ffmpeg failed to extract frames (exit code: {:?})", status.code()));
     }
     
-    info!("Frame extraction completed successfully");
+    info!("Frame extraction completed successfully ({} timestamps)", timestamps.len());
+    Ok(timestamps)
+}
+
+/// Seek the input of `cmd` to the `(start, end)` range, returning the position
+/// seeked to, from which ffmpeg restarts the timestamps.
+///
+/// Times are floored to whole milliseconds: rounding `start` up could skip the
+/// frame shown at `start`.
+fn seek_range(cmd: &mut Command, start: f64, end: f64) -> f64 {
+    let (start, end) = (floor_millis(start), floor_millis(end));
+    cmd.args(["-ss", &millis_arg(start), "-t", &millis_arg(end.saturating_sub(start))]);
+    start as f64 / 1000.0
+}
+
+/// Whole milliseconds in `seconds`, rounded down
+fn floor_millis(seconds: f64) -> u64 {
+    // Absorb float error in times like 0.3s (299.99999... ms)
+    (seconds.max(0.0) * 1000.0 + 1e-6).floor() as u64
+}
+
+/// Format milliseconds as seconds for ffmpeg's time options
+fn millis_arg(ms: u64) -> String {
+    format!("{}.{:03}", ms / 1000, ms % 1000)
+}
+
+/// Extract one frame per interval as `frame_%06d.<ext>` (numbered from 1) into `outdir`.
+///
+/// Each frame is taken from the middle of its interval, away from the
+/// transitions, but its returned timestamp is the interval start; together with
+/// the interval end as [`FrameEntry::end`] the frames carry the interval
+/// durations through clustering and merging.
+#[instrument(name = "extract_interval_frames", skip(config, intervals))]
+fn extract_interval_frames(
+    config: &Config,
+    outdir: &Path,
+    intervals: &[(f64, f64)],
+) -> Result<Vec<f64>> {
+    fs::create_dir_all(outdir)
+        .with_context(|| format!("Failed to create frames directory: {}", outdir.display()))?;
+    info!("Extracting {} frames to {}", intervals.len(), outdir.display());
+
+    intervals.par_iter().enumerate().try_for_each(|(i, (start, end))| {
+        let path = outdir.join(format!("frame_{:06}.{}", i + 1, config.format.ext()));
+        extract_frame_at(config, (start + end) / 2.0, &path)
+    })?;
+    Ok(intervals.iter().map(|&(start, _)| start).collect())
+}
+
+/// Scenes of the input as `(start, end)` times, split at ffmpeg scene changes.
+///
+/// Scene scores are computed between the frames sampled at `config.fps`.
+#[instrument(name = "detect_scenes", skip(config))]
+fn detect_scenes(config: &Config) -> Result<Vec<(f64, f64)>> {
+    info!("Detecting scene changes above score {}", config.scene_threshold);
+    let filter = format!(
+        "{},select='gt(scene,{})',showinfo",
+        config.fps_select(), config.scene_threshold
+    );
+    let mut changes = Vec::new();
+    let duration = run_detection(config, &filter, "detect scenes", |line| {
+        match parse_showinfo(line) {
+            Some((_, pts_time)) => changes.push(pts_time),
+            None => trace!("ffmpeg: {}", line),
+        }
+    })?;
+    info!("Detected {} scene changes", changes.len());
+
+    let mut starts = vec![0.0];
+    starts.extend(changes.into_iter().filter(|&t| t > 0.0));
+    // Without a known duration the last scene is sampled one frame after its start
+    let last = starts[starts.len() - 1];
+    let end = duration.unwrap_or(last + 1.0 / f64::from(config.fps)).max(last);
+    let ends = starts.iter().skip(1).copied().chain(std::iter::once(end));
+    Ok(starts.iter().copied().zip(ends).collect())
+}
+
+/// Frozen intervals of the input as `(start, end)` times, from ffmpeg `freezedetect`.
+///
+/// A freeze still running at the end of the input ends with the input.
+#[instrument(name = "detect_freezes", skip(config))]
+fn detect_freezes(config: &Config) -> Result<Vec<(f64, f64)>> {
+    info!(
+        "Detecting frozen intervals (noise {}, at least {}s)",
+        config.freeze_noise, config.freeze_duration
+    );
+    let filter = format!(
+        "{},freezedetect=n={}:d={}",
+        config.fps_select(), config.freeze_noise, config.freeze_duration
+    );
+    let mut intervals = Vec::new();
+    let mut open = None;
+    let duration = run_detection(config, &filter, "detect frozen intervals", |line| {
+        let value = |key: &str| -> Option<f64> {
+            let rest = &line[line.find(key)? + key.len()..];
+            rest.split_whitespace().next()?.parse().ok()
+        };
+        if let Some(start) = value("lavfi.freezedetect.freeze_start:") {
+            open = Some(start);
+        } else if let Some(end) = value("lavfi.freezedetect.freeze_end:") {
+            if let Some(start) = open.take() {
+                intervals.push((start, end));
+            }
+        } else {
+            trace!("ffmpeg: {}", line);
+        }
+    })?;
+    if let Some(start) = open {
+        let end = duration.unwrap_or(start + f64::from(config.freeze_duration));
+        intervals.push((start, end.max(start)));
+    }
+
+    if intervals.is_empty() {
+        warn!(
+            "No frozen intervals found; try a higher --freeze-noise or a shorter \
+             --freeze-duration"
+        );
+    }
+    info!("Detected {} frozen intervals", intervals.len());
+    Ok(intervals)
+}
+
+/// Run an analysis-only ffmpeg pass of `filter` over the input, discarding the output.
+///
+/// Filter log lines are handed to `on_info`. Returns the input duration, when
+/// ffmpeg reports it.
+fn run_detection(
+    config: &Config,
+    filter: &str,
+    what: &str,
+    mut on_info: impl FnMut(&str),
+) -> Result<Option<f64>> {
+    let mut cmd = Command::new(&config.ffmpeg_bin);
+    cmd.args(["-hide_banner", "-nostats", "-loglevel", "level+info", "-i"])
+        .arg(&config.input)
+        .args(["-vf", filter, "-f", "null", "-"]);
+
+    let mut duration = None;
+    let status = run_with_log(&mut cmd, |line| match parse_duration(line) {
+        Some(d) => {
+            duration.get_or_insert(d);
+        }
+        None => on_info(line),
+    })?;
+    if !status.success() {
+        return Err(anyhow!("ffmpeg failed to {} (exit code: {:?})", what, status.code()));
+    }
+    Ok(duration)
+}
+
+/// Parse the input duration in seconds from ffmpeg's `Duration: HH:MM:SS.ss, ...` line
+fn parse_duration(line: &str) -> Option<f64> {
+    let rest = &line[line.find("Duration: ")? + "Duration: ".len()..];
+    parse_timestamp(rest.split(',').next()?.trim())
+}
+
+/// Extract the frame shown at `time` seconds into `path`, in the output format
+fn extract_frame_at(config: &Config, time: f64, path: &Path) -> Result<()> {
+    let mut cmd = Command::new(&config.ffmpeg_bin);
+    cmd.args(["-hide_banner", "-nostats", "-loglevel", "level+info"])
+        .args(["-ss", &millis_arg(floor_millis(time)), "-i"])
+        .arg(&config.input)
+        .args(["-frames:v", "1", "-update", "1"])
+        .args(config.format.encoder_args(config.webp_lossless))
+        .arg("-y")
+        .arg(path);
+    let status = run_with_log(&mut cmd, |line| trace!("ffmpeg: {}", line))?;
+    if !status.success() || !path.exists() {
+        return Err(anyhow!(
+            "ffmpeg failed to extract the frame at {:.3}s (exit code: {:?})",
+            time,
+            status.code()
+        ));
+    }
+    Ok(())
+}
+
+/// Width of the grayscale thumbnails used for pixel-level frame comparisons
+const THUMB_WIDTH: u32 = 160;
+
+/// Load a small grayscale thumbnail of every frame, in frame order
+#[instrument(name = "load_thumbnails", skip(frames))]
+fn load_thumbnails(frames: &[FrameEntry]) -> Result<Vec<GrayImage>> {
+    debug!("Loading thumbnails for {} frames", frames.len());
+    frames.par_iter().map(|frame| load_thumbnail(&frame.path)).collect()
+}
+
+/// Load the grayscale thumbnail of one frame image
+fn load_thumbnail(path: &Path) -> Result<GrayImage> {
+    let img = image::open(path)
+        .with_context(|| format!("Failed to open frame image: {}", path.display()))?;
+    Ok(thumbnail(&img))
+}
+
+/// Grayscale thumbnail of a frame, [`THUMB_WIDTH`] pixels wide
+fn thumbnail(img: &image::DynamicImage) -> GrayImage {
+    let height = (img.height() * THUMB_WIDTH / img.width().max(1)).max(1);
+    img.thumbnail_exact(THUMB_WIDTH, height).to_luma8()
+}
+
+/// Pixel bounds `(x, y, w, h)` of a region of the frame
+type PixelRect = (u32, u32, u32, u32);
+
+/// Per-video adjustments applied to every frame, detected before hashing.
+///
+/// Frames are first rectified to the projector screen, if any; the mask and the
+/// content area refer to the rectified frame.
+#[derive(Debug, Clone, Default, PartialEq)]
+struct FramePrep {
+    /// Projector screen in frame pixels
+    screen: Option<Quad>,
+    /// Hashing mask: the configured regions plus auto-detected dynamic ones
+    mask: RegionMask,
+    /// Content area inside letterbox/pillarbox bars, in frame pixels
+    content: Option<PixelRect>,
+}
+
+impl FramePrep {
+    /// Warp a frame to the front-on screen image (unchanged without a screen)
+    fn rectified<P>(&self, img: ImageBuffer<P, Vec<u8>>) -> ImageBuffer<P, Vec<u8>>
+    where
+        P: Pixel<Subpixel = u8>,
+    {
+        match self.screen.and_then(|quad| quad.rectify(&img)) {
+            Some(warped) => warped,
+            None => img,
+        }
+    }
+
+    /// The same preparation for frames resized by `sx` horizontally and `sy` vertically
+    fn scaled(&self, sx: f32, sy: f32) -> FramePrep {
+        let scale = |v: u32, s: f32| (v as f32 * s).round() as u32;
+        FramePrep {
+            screen: self.screen.map(|quad| quad.scaled(sx, sy)),
+            mask: self.mask.scaled(sx, sy),
+            content: self
+                .content
+                .map(|(x, y, w, h)| (scale(x, sx), scale(y, sy), scale(w, sx), scale(h, sy))),
+        }
+    }
+
+    /// Whether written slides differ from the extracted frames and must be re-encoded
+    fn transforms_output(&self, config: &Config) -> bool {
+        config.crop_output || self.content.is_some() || self.screen.is_some()
+    }
+
+    /// Crop applied to written slides: the content area, narrowed to the include
+    /// regions with `--crop-output`
+    fn output_crop(&self, config: &Config, width: u32, height: u32) -> Option<PixelRect> {
+        let include = if config.crop_output {
+            self.mask.include_bounds(width, height)
+        } else {
+            None
+        };
+        match (self.content, include) {
+            (Some((cx, cy, cw, ch)), Some((ix, iy, iw, ih))) => {
+                let (x0, y0) = (cx.max(ix), cy.max(iy));
+                let x1 = (cx + cw).min(ix + iw).max(x0);
+                let y1 = (cy + ch).min(iy + ih).max(y0);
+                Some((x0, y0, x1 - x0, y1 - y0))
+            }
+            (content, include) => content.or(include),
+        }
+    }
+}
+
+/// Locate the projector screen, letterbox bars and dynamic regions on thumbnails
+/// of the extracted frames
+#[instrument(name = "prepare_frames", skip(config, files))]
+fn prepare_frames(config: &Config, files: &[(usize, PathBuf)]) -> Result<FramePrep> {
+    let Some((_, first)) = files.first() else {
+        return Ok(FramePrep { mask: config.mask.clone(), ..FramePrep::default() });
+    };
+    if !needs_preparation(config) {
+        return Ok(FramePrep { mask: config.mask.clone(), ..FramePrep::default() });
+    }
+
+    let (width, height) = image::image_dimensions(first)
+        .with_context(|| format!("Failed to read frame size: {}", first.display()))?;
+    let thumbs: Vec<GrayImage> = files
+        .par_iter()
+        .map(|(_, path)| load_thumbnail(path))
+        .collect::<Result<_>>()?;
+    prepare_from_thumbnails(config, thumbs, width, height)
+}
+
+/// Whether the configuration needs a pass over all frames before hashing
+fn needs_preparation(config: &Config) -> bool {
+    config.autocrop || config.auto_mask || config.rectify || config.corners.is_some()
+}
+
+/// [`prepare_frames`] from the thumbnails of all frames of a `width` x `height` video
+fn prepare_from_thumbnails(
+    config: &Config,
+    mut thumbs: Vec<GrayImage>,
+    mut width: u32,
+    mut height: u32,
+) -> Result<FramePrep> {
+    let mut prep = FramePrep { mask: config.mask.clone(), ..FramePrep::default() };
+    if thumbs.is_empty() {
+        return Ok(prep);
+    }
+
+    let (thumb_w, thumb_h) = thumbs[0].dimensions();
+    let (sx, sy) = (width as f32 / thumb_w as f32, height as f32 / thumb_h as f32);
+    let screen = match config.corners {
+        Some(corners) => Some(corners.to_quad(width, height)),
+        None if config.rectify => {
+            let quad = detect_screen_quad(&thumbs).map(|q| q.scaled(sx, sy));
+            if quad.is_none() {
+                warn!("No projector screen detected, frames are not rectified");
+            }
+            quad
+        }
+        None => None,
+    };
+    if let Some(quad) = screen {
+        let thumb_quad = quad.scaled(1.0 / sx, 1.0 / sy);
+        match thumbs.par_iter().map(|t| thumb_quad.rectify(t)).collect::<Option<Vec<_>>>() {
+            Some(rectified) => {
+                thumbs = rectified;
+                (width, height) = quad.size();
+                prep.screen = Some(quad);
+                info!("Rectifying screen with corners {:?} to {}x{}", quad.corners, width, height);
+            }
+            None => warn!("Degenerate screen corners {:?}, frames are not rectified", quad.corners),
+        }
+    }
+
+    if config.autocrop {
+        prep.content = detect_content_area(&thumbs).map(|r| r.to_pixels(width, height));
+        match prep.content {
+            Some((x, y, w, h)) => {
+                info!("Cropping bars: content area is {}x{} at ({}, {})", w, h, x, y)
+            }
+            None => debug!("No letterbox or pillarbox bars detected"),
+        }
+    }
+    if config.auto_mask {
+        prep.mask = detect_mask(config, &thumbs, width, height)?;
+    }
+    Ok(prep)
+}
+
+/// Extend the configured mask with automatically detected dynamic regions.
+///
+/// The effective mask is saved as `mask.png` in the output directory (white =
+/// hashed, black = ignored).
+fn detect_mask(config: &Config, thumbs: &[GrayImage], width: u32, height: u32) -> Result<RegionMask> {
+    let regions = detect_dynamic_regions(thumbs);
+
+    let mut mask = config.mask.clone();
+    if regions.is_empty() {
+        info!("No dynamic regions detected");
+    }
+    for region in &regions {
+        let (x, y, w, h) = region.to_pixels(width, height);
+        info!("Excluding dynamic region {}x{} at ({}, {}) from hashing", w, h, x, y);
+    }
+    mask.exclude.extend(regions);
+    save_mask(config, &mask, width, height)?;
+    Ok(mask)
+}
+
+/// Save `mask` of a `width` x `height` frame as `mask.png` in the output directory.
+///
+/// The directory is created if needed: `tune` and `eval` write nothing else there.
+fn save_mask(config: &Config, mask: &RegionMask, width: u32, height: u32) -> Result<()> {
+    fs::create_dir_all(&config.out_dir).with_context(|| {
+        format!("Failed to create output directory: {}", config.out_dir.display())
+    })?;
+    let mut preview = image::RgbaImage::from_pixel(width, height, image::Rgba([255, 255, 255, 255]));
+    mask.apply(&mut preview);
+    let mask_path = config.out_dir.join("mask.png");
+    preview
+        .save(&mask_path)
+        .with_context(|| format!("Failed to write {}", mask_path.display()))?;
+    info!("Wrote hashing mask to {}", mask_path.display());
     Ok(())
 }
 
-#[instrument(name = "load_hashes", skip(dir))]
-fn load_frame_hashes(dir: &Path) -> Result<Vec<FrameEntry>> {
-    debug!("Loading frame hashes from: {}", dir.display());
+/// Sharpness (Laplacian variance at full resolution) of every frame of a cluster
+fn load_sharpness(cluster: &[usize], frames: &[FrameEntry]) -> Result<Vec<f64>> {
+    cluster
+        .iter()
+        .map(|&f| -> Result<f64> {
+            let path = &frames[f].path;
+            let img = image::open(path)
+                .with_context(|| format!("Failed to open frame image: {}", path.display()))?;
+            Ok(laplacian_variance(&img.to_luma8()))
+        })
+        .collect()
+}
+
+/// List the extracted frame files in `dir` with their frame numbers, in order
+fn list_frame_files(dir: &Path) -> Result<Vec<(usize, PathBuf)>> {
+    debug!("Listing frame files in: {}", dir.display());
 
     // Collect and sort paths by numeric index (…_%06d.ext)
     let mut entries: Vec<(usize, PathBuf)> = WalkDir::new(dir)
@@ -473,30 +2686,36 @@ fn load_frame_hashes(dir: &Path) -> Result<Vec<FrameEntry>> {
 
     entries.par_sort_by_key(|(i, _)| *i);
     info!("Found {} frame files to process", entries.len());
+    Ok(entries)
+}
+
+/// Hash the extracted frames, attaching their timestamps.
+///
+/// `timestamps[n - 1]` is the presentation time of `frame_<n>`; frames without a
+/// reported time fall back to `(n - 1) / fps`. Frames are rectified, masked and
+/// cropped to the content area as described by `prep`.
+#[instrument(name = "load_hashes", skip(config, entries, timestamps, prep))]
+fn load_frame_hashes(
+    config: &Config,
+    entries: &[(usize, PathBuf)],
+    timestamps: &[f64],
+    prep: &FramePrep,
+) -> Result<Vec<FrameEntry>> {
+    debug!("Loading frame hashes for {} frames", entries.len());
+    warn_missing_pts(entries.iter().map(|(idx, _)| *idx), timestamps);
 
     // Parallel load + hash with better error handling
     // Create a separate hasher for each thread to avoid Send/Sync issues
     let results: Vec<Result<FrameEntry>> = entries
         .par_iter()
         .map(|(idx, path)| -> Result<FrameEntry> {
-            // DCT 8x8 = 64-bit perceptual hash (create per-thread to avoid sync issues)
-            let hasher = HasherConfig::new().hash_size(8, 8).to_hasher();
-            
             let dynimg = image::open(path)
                 .with_context(|| format!("Failed to open frame image: {}", path.display()))?;
-            let rgba = dynimg.to_rgba8();
-            let (w, h) = rgba.dimensions();
-            let raw = rgba.into_raw();
-            
-            let buf = img_hash::image::ImageBuffer::<img_hash::image::Rgba<u8>, Vec<u8>>::from_raw(w, h, raw)
-                .ok_or_else(|| anyhow!("Failed to build image buffer for hashing: {}", path.display()))?;
-            let hash = hasher.hash_image(&buf);
-            
-            Ok(FrameEntry {
-                idx: *idx,
-                path: path.clone(),
-                hash,
-            })
+            let (hash, multi) = hash_frame(config, prep, dynimg.to_rgba8())
+                .with_context(|| format!("Failed to hash frame: {}", path.display()))?;
+
+            let pts = frame_pts(config, *idx, timestamps);
+            Ok(FrameEntry::new(*idx, pts, path.clone(), hash).with_multi(multi))
         })
         .collect();
 
@@ -525,3 +2744,492 @@ fn load_frame_hashes(dir: &Path) -> Result<Vec<FrameEntry>> {
     info!("Successfully loaded and hashed {} frames", successful.len());
     Ok(successful)
 }
+
+/// Hash one decoded frame after applying the frame preparation
+fn hash_frame(
+    config: &Config,
+    prep: &FramePrep,
+    rgba: image::RgbaImage,
+) -> Result<(ImageHash, Option<MultiHash>)> {
+    // 8x8 = 64-bit perceptual hashes (create per-thread to avoid sync issues)
+    let hashers: Vec<Hasher> = config.hashes.iter().map(|k| k.hasher()).collect();
+
+    let mut rgba = prep.rectified(rgba);
+    prep.mask.apply(&mut rgba);
+    if let Some((x, y, w, h)) = prep.content {
+        rgba = image::imageops::crop_imm(&rgba, x, y, w.max(1), h.max(1)).to_image();
+    }
+    let (w, h) = rgba.dimensions();
+    let raw = rgba.into_raw();
+
+    let buf =
+        img_hash::image::ImageBuffer::<img_hash::image::Rgba<u8>, Vec<u8>>::from_raw(w, h, raw)
+            .ok_or_else(|| anyhow!("Failed to build image buffer for hashing"))?;
+    let mut hashes = hashers.iter().map(|h| h.hash_image(&buf));
+    let hash = hashes.next().expect("at least one hash type is configured");
+    let extra: Vec<_> = hashes.collect();
+    let multi = (!extra.is_empty()).then(|| MultiHash {
+        extra,
+        weights: Arc::clone(&config.hash_weights),
+        fusion: config.fusion.into(),
+    });
+    Ok((hash, multi))
+}
+
+/// Timestamp of frame number `idx` (from 1) as reported by ffmpeg, if any
+fn reported_pts(idx: usize, timestamps: &[f64]) -> Option<f64> {
+    let n = idx.checked_sub(1)?;
+    timestamps.get(n).copied().filter(|t| t.is_finite())
+}
+
+/// Timestamp of frame number `idx` (from 1), falling back to the nominal frame rate
+fn frame_pts(config: &Config, idx: usize, timestamps: &[f64]) -> f64 {
+    reported_pts(idx, timestamps)
+        .unwrap_or_else(|| idx.saturating_sub(1) as f64 / f64::from(config.fps))
+}
+
+/// Warn once if any of the frames `indices` lacks a reported timestamp
+fn warn_missing_pts(indices: impl IntoIterator<Item = usize>, timestamps: &[f64]) {
+    let missing = indices
+        .into_iter()
+        .filter(|&idx| reported_pts(idx, timestamps).is_none())
+        .count();
+    if missing > 0 {
+        warn!("No timestamp reported for {} frames, assuming constant frame rate", missing);
+    }
+}
+
+/// [`analyze_video`] reading the sampled frames from an ffmpeg rawvideo pipe.
+///
+/// Frames are hashed in memory; their entries point to `frame_%06d.<ext>` files
+/// in `dir` that are only extracted when needed (see [`ensure_frame_files`]).
+/// When the frame preparation must see every frame first, the video is decoded
+/// twice: once for thumbnails, downscaled by ffmpeg to [`THUMB_WIDTH`] grayscale,
+/// once for hashing. With `--hash-width` ffmpeg downscales the hashed frames too;
+/// the preparation refers to the full-size frames of the input stream and is
+/// scaled down for hashing.
+#[instrument(name = "analyze_piped", skip(config))]
+fn analyze_piped(config: &Config, dir: &Path) -> Result<(Vec<FrameEntry>, FramePrep)> {
+    let prep = if needs_preparation(config) {
+        info!("Decoding frames for thumbnails");
+        let mut thumbs = Vec::new();
+        let mut size = (0, 0);
+        pipe_frames(config, Some(THUMB_WIDTH), None, |frame| {
+            size = frame.source_size;
+            thumbs.push(frame.image.into_luma8());
+            Ok(())
+        })?;
+        prepare_from_thumbnails(config, thumbs, size.0, size.1)?
+    } else {
+        FramePrep { mask: config.mask.clone(), ..FramePrep::default() }
+    };
+
+    info!("Decoding and hashing frames");
+    let frames = hash_piped(config, &prep, None, dir)?;
+    info!("Successfully decoded and hashed {} frames", frames.len());
+    Ok((frames, prep))
+}
+
+/// Decode the sampled frames of the video, or of its `(start, end)` range, through
+/// [`pipe_frames`] at `--hash-width` and hash them as prepared by `prep`.
+///
+/// The entries point to `frame_%06d.<ext>` files in `dir`, extracted only when
+/// needed (see [`ensure_frame_files`]).
+fn hash_piped(
+    config: &Config,
+    prep: &FramePrep,
+    range: Option<(f64, f64)>,
+    dir: &Path,
+) -> Result<Vec<FrameEntry>> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create frames directory: {}", dir.display()))?;
+
+    // Frames are hashed in parallel batches as they arrive
+    let batch_size = 2 * rayon::current_num_threads();
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut hashed = Vec::new();
+    let mut hash_prep = None;
+    let timestamps = pipe_frames(config, config.hash_width, range, |frame| {
+        let hash_prep = hash_prep.get_or_insert_with(|| {
+            let (width, height) = frame.source_size;
+            let sx = frame.image.width() as f32 / width.max(1) as f32;
+            let sy = frame.image.height() as f32 / height.max(1) as f32;
+            prep.scaled(sx, sy)
+        });
+        batch.push((frame.idx, frame.image.into_rgba8()));
+        if batch.len() == batch_size {
+            hash_batch(config, hash_prep, &mut batch, &mut hashed)?;
+        }
+        Ok(())
+    })?;
+    hash_batch(config, hash_prep.as_ref().unwrap_or(prep), &mut batch, &mut hashed)?;
+
+    warn_missing_pts(hashed.iter().map(|(idx, _)| *idx), &timestamps);
+    let ext = config.format.ext();
+    Ok(hashed
+        .into_iter()
+        .map(|(idx, (hash, multi))| {
+            let path = dir.join(format!("frame_{:06}.{}", idx, ext));
+            FrameEntry::new(idx, frame_pts(config, idx, &timestamps), path, hash).with_multi(multi)
+        })
+        .collect())
+}
+
+/// A hashed frame number with its primary and extra hashes
+type HashedFrame = (usize, (ImageHash, Option<MultiHash>));
+
+/// Hash and drain a batch of decoded frames in parallel, appending to `hashed`
+fn hash_batch(
+    config: &Config,
+    prep: &FramePrep,
+    batch: &mut Vec<(usize, image::RgbaImage)>,
+    hashed: &mut Vec<HashedFrame>,
+) -> Result<()> {
+    let hashes: Vec<HashedFrame> = batch
+        .par_drain(..)
+        .map(|(idx, img)| Ok((idx, hash_frame(config, prep, img)?)))
+        .collect::<Result<_>>()?;
+    hashed.extend(hashes);
+    Ok(())
+}
+
+/// A frame decoded by [`pipe_frames`]
+struct PipedFrame {
+    /// Frame number, from 1
+    idx: usize,
+    /// RGBA frame, or grayscale when downscaled by ffmpeg
+    image: image::DynamicImage,
+    /// Size of the frames in the input video
+    source_size: (u32, u32),
+}
+
+/// Source and output frame sizes, as sent by [`PipeLog`]
+type PipeSizes = ((u32, u32), (u32, u32));
+
+/// State of the ffmpeg log of [`pipe_frames`]
+struct PipeLog {
+    /// Receives the frame sizes once the output video stream is logged; dropped
+    /// after that line, or with the log, so the frame reader never waits forever
+    size_tx: Option<std::sync::mpsc::Sender<PipeSizes>>,
+    /// Start of the decoded range, added to the reported timestamps
+    offset: f64,
+    /// Presentation timestamps by output frame number
+    timestamps: Vec<f64>,
+    /// Log section being read
+    section: PipeLogSection,
+    /// Id and size of each input video stream, without attached pictures
+    inputs: Vec<(String, (u32, u32))>,
+    /// Id of the input stream decoded into the output, from the stream mapping
+    mapped: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PipeLogSection {
+    Preamble,
+    Input,
+    Mapping,
+    Output,
+}
+
+impl PipeLog {
+    fn new(size_tx: std::sync::mpsc::Sender<PipeSizes>, offset: f64) -> Self {
+        PipeLog {
+            size_tx: Some(size_tx),
+            offset,
+            timestamps: Vec::new(),
+            section: PipeLogSection::Preamble,
+            inputs: Vec::new(),
+            mapped: None,
+        }
+    }
+
+    /// Size of the decoded input stream: the mapped one, or the one ffmpeg picks
+    /// by default, the largest video stream
+    fn source_size(&self) -> Option<(u32, u32)> {
+        let mapped = self.mapped.as_ref().and_then(|id| self.inputs.iter().find(|(i, _)| i == id));
+        mapped
+            .or_else(|| self.inputs.iter().max_by_key(|(_, (w, h))| u64::from(*w) * u64::from(*h)))
+            .map(|&(_, size)| size)
+    }
+
+    /// Handle one info line of the log
+    fn line(&mut self, line: &str) {
+        // showinfo may report frames before the output is set up and logged
+        if let Some((n, pts_time)) = parse_showinfo(line) {
+            if self.timestamps.len() <= n {
+                self.timestamps.resize(n + 1, f64::NAN);
+            }
+            // Input seeking restarts the timestamps at the seek point
+            self.timestamps[n] = self.offset + pts_time;
+            return;
+        }
+        trace!("ffmpeg: {}", line);
+        if line.contains("Input #") {
+            self.section = PipeLogSection::Input;
+        } else if line.contains("Stream mapping:") {
+            self.section = PipeLogSection::Mapping;
+        } else if line.contains("Output #0") {
+            self.section = PipeLogSection::Output;
+        } else if let Some(id) = parse_stream_id(line) {
+            let video = line.split_once("Video:").map(|(_, desc)| desc);
+            match (self.section, video) {
+                (PipeLogSection::Input, Some(desc)) if !desc.contains("(attached pic)") => {
+                    if let Some(size) = parse_frame_size(desc) {
+                        self.inputs.push((id.to_string(), size));
+                    }
+                }
+                (PipeLogSection::Mapping, _) => {
+                    self.mapped.get_or_insert_with(|| id.to_string());
+                }
+                (PipeLogSection::Output, Some(desc)) => {
+                    if let Some(tx) = self.size_tx.take() {
+                        if let Some(size) = parse_frame_size(desc) {
+                            let _ = tx.send((self.source_size().unwrap_or(size), size));
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Decode the frames sampled at `config.fps` from an ffmpeg rawvideo pipe.
+///
+/// Frames are RGBA at full resolution, or grayscale downscaled by ffmpeg to
+/// `width`. Each frame is handed to `on_frame` in order. Returns the
+/// presentation timestamps like [`extract_frames`], also for a `(start, end)`
+/// range, which is always decoded at `config.fps`.
+#[instrument(name = "pipe_frames", skip(config, on_frame))]
+fn pipe_frames(
+    config: &Config,
+    width: Option<u32>,
+    range: Option<(f64, f64)>,
+    mut on_frame: impl FnMut(PipedFrame) -> Result<()>,
+) -> Result<Vec<f64>> {
+    let (rest, pix_fmt, channels) = match width {
+        Some(width) => (format!("scale={}:-2,format=gray,showinfo", width), "gray", 1),
+        None => ("showinfo".to_string(), "rgba", 4),
+    };
+    let mut cmd = Command::new(&config.ffmpeg_bin);
+    cmd.args(["-hide_banner", "-nostats", "-loglevel", "level+info"]);
+    let (filter, offset) = match range {
+        Some((start, end)) => {
+            (format!("{},{}", config.fps_select(), rest), seek_range(&mut cmd, start, end))
+        }
+        None => {
+            cmd.args(config.decode_args());
+            (config.sample_filter(&rest), 0.0)
+        }
+    };
+    cmd.arg("-i")
+        .arg(&config.input)
+        .args(["-vf", &filter, "-vsync", "vfr"])
+        .args(["-f", "rawvideo", "-pix_fmt", pix_fmt, "-"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    debug!("Executing command: {}", format_command(&cmd));
+
+    let mut child = cmd.spawn().context("Failed to spawn command")?;
+    let (Some(mut stdout), Some(stderr)) = (child.stdout.take(), child.stderr.take()) else {
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(anyhow!("Failed to capture command output"));
+    };
+
+    // The log is read on its own thread: it reports the input and output frame
+    // sizes, needed to split stdout into frames, and the frame timestamps
+    let (size_tx, size_rx) = std::sync::mpsc::channel();
+    let log = std::thread::spawn(move || -> Result<Vec<f64>> {
+        let mut log = PipeLog::new(size_tx, offset);
+        route_log(stderr, &mut |line: &str| log.line(line))?;
+        Ok(log.timestamps)
+    });
+
+    let mut read = || -> Result<usize> {
+        let mut frames = 0;
+        let Ok((source_size, (width, height))) = size_rx.recv() else {
+            return Ok(frames);
+        };
+        debug!("Reading {}x{} frames", width, height);
+        let mut buf = vec![0u8; width as usize * height as usize * channels];
+        loop {
+            match stdout.read_exact(&mut buf) {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(frames),
+                Err(e) => return Err(e).context("Failed reading frames from ffmpeg"),
+            }
+            let image = match channels {
+                1 => GrayImage::from_raw(width, height, buf.clone())
+                    .map(image::DynamicImage::ImageLuma8),
+                _ => image::RgbaImage::from_raw(width, height, buf.clone())
+                    .map(image::DynamicImage::ImageRgba8),
+            }
+            .context("Frame buffer does not match the frame size")?;
+            frames += 1;
+            on_frame(PipedFrame { idx: frames, image, source_size })?;
+        }
+    };
+    let frames = read();
+    drop(stdout);
+    if frames.is_err() {
+        // ffmpeg may be blocked writing frames nobody reads any more
+        let _ = child.kill();
+    }
+
+    let status = child.wait().context("Failed waiting for command")?;
+    let timestamps = log.join().map_err(|_| anyhow!("ffmpeg log reader panicked"));
+    let frames = frames?;
+    let timestamps = timestamps??;
+    if frames == 0 && timestamps.iter().any(|t| t.is_finite()) {
+        return Err(anyhow!("Could not determine the size of the frames decoded by ffmpeg"));
+    }
+    if !status.success() {
+        return Err(anyhow!("ffmpeg failed to decode frames (exit code: {:?})", status.code()));
+    }
+    debug!("Decoded {} frames ({} timestamps)", frames, timestamps.len());
+    Ok(timestamps)
+}
+
+/// Parse the id (`#0:1`) of the first stream named on an ffmpeg log line
+fn parse_stream_id(line: &str) -> Option<&str> {
+    let rest = &line[line.find("Stream #")? + "Stream ".len()..];
+    let end = rest.find(['[', '(', ' ']).unwrap_or(rest.len());
+    Some(rest[..end].trim_end_matches(':'))
+}
+
+/// Parse the `WIDTHxHEIGHT` frame size from an ffmpeg stream description line
+fn parse_frame_size(line: &str) -> Option<(u32, u32)> {
+    line.split_whitespace().find_map(|token| {
+        let (w, h) = token.trim_end_matches(',').split_once('x')?;
+        let size = (w.parse().ok()?, h.parse().ok()?);
+        (size.0 > 0 && size.1 > 0).then_some(size)
+    })
+}
+
+/// Make sure the image files of the given representative frames exist, extracting
+/// missing ones from the video at their timestamps (frames decoded with `--pipe`
+/// have none)
+fn ensure_frame_files(config: &Config, frames: &[FrameEntry], indices: &[usize]) -> Result<()> {
+    let missing: Vec<&FrameEntry> =
+        indices.iter().map(|&f| &frames[f]).filter(|f| !f.path.exists()).collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    debug!("Extracting {} frames from the video", missing.len());
+    missing
+        .par_iter()
+        .try_for_each(|frame| extract_frame_at(config, frame.pts, &frame.path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_index_round_trips() {
+        let entry = TempDir::new().unwrap();
+
+        let prep = FramePrep {
+            screen: Some(Quad { corners: [(0.5, 1.25), (99.75, 0.0), (100.0, 60.0), (0.1, 59.9)] }),
+            mask: RegionMask {
+                include: vec!["0.00001,0,1.0,0.5".parse().unwrap()],
+                exclude: vec![
+                    "10,20,30,40".parse().unwrap(),
+                    "0.75,0.75,0.25,0.25".parse().unwrap(),
+                ],
+            },
+            content: Some((1, 2, 97, 55)),
+        };
+        let frames: Vec<FrameEntry> = [0.0, 1.25, 2.0 / 3.0]
+            .iter()
+            .enumerate()
+            .map(|(i, &pts)| {
+                let path = entry.path().join(format!("frame_{:06}.png", i + 1));
+                fs::write(&path, b"").unwrap();
+                let hash = ImageHash::from_bytes(&[i as u8, 0xA5, 0, 0, 0, 0, 0, 0xFF]).unwrap();
+                FrameEntry::new(i + 1, pts, path, hash).with_end((i == 1).then_some(1.5))
+            })
+            .collect();
+
+        write_cache(entry.path(), &frames, &prep).unwrap();
+        let weights: Arc<[f32]> = Arc::from([1.0]);
+        let (read, read_prep) = read_cache(entry.path(), &weights, HashFusion::Weighted).unwrap();
+        assert_eq!(read_prep, prep);
+        assert_eq!(read.len(), frames.len());
+        for (a, b) in read.iter().zip(&frames) {
+            assert_eq!((a.idx, a.pts, a.end), (b.idx, b.pts, b.end));
+            assert_eq!((&a.path, &a.hash), (&b.path, &b.hash));
+        }
+    }
+
+    #[test]
+    fn pipe_log_sends_sizes_logged_after_the_first_frames() {
+        // Recent ffmpeg sets up the output, and logs it, only after the filters
+        // produced the first frame
+        let log = [
+            "[info] Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'talk.mp4':",
+            "[info]   Stream #0:0[0x1](und): Video: h264 (High) (avc1 / 0x31637661), \
+             yuv420p(progressive), 1920x1080 [SAR 1:1 DAR 16:9], 30 fps, 30 tbr",
+            "[info] Stream mapping:",
+            "[info]   Stream #0:0 -> #0:0 (h264 (native) -> rawvideo (native))",
+            "[info] [Parsed_showinfo_2 @ 0x5581] n:   0 pts:      0 pts_time:0       \
+             duration:1 checksum:5A3E",
+            "[info] [Parsed_showinfo_2 @ 0x5581] n:   1 pts:      2 pts_time:1       \
+             duration:1 checksum:77C1",
+            "[info] Output #0, rawvideo, to 'pipe:':",
+            "[info]   Stream #0:0(und): Video: rawvideo (Y800 / 0x30303859), gray(pc), \
+             64x36 [SAR 1:1 DAR 16:9], q=2-31, 2 fps, 2 tbn",
+        ];
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut pipe_log = PipeLog::new(tx, 10.0);
+        for line in log {
+            pipe_log.line(line);
+        }
+        assert_eq!(pipe_log.timestamps, vec![10.0, 11.0]);
+        assert_eq!(rx.try_recv(), Ok(((1920, 1080), (64, 36))));
+        // Only the first output video line is used
+        pipe_log.line("[info]   Stream #0:1: Video: rawvideo, rgba, 8x8");
+        drop(pipe_log);
+        assert!(rx.recv().is_err());
+    }
+
+    #[test]
+    fn pipe_log_reports_the_size_of_the_mapped_stream() {
+        let log = [
+            "[info] Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'talk.mp4':",
+            "[info]   Stream #0:0[0x1](und): Video: mjpeg (Baseline), yuvj420p(pc), \
+             600x600, 90k tbr (attached pic)",
+            "[info]   Stream #0:1[0x2](und): Video: h264 (High), yuv420p, 640x360, 25 fps",
+            "[info]   Stream #0:2[0x3](und): Video: h264 (High), yuv420p, 1280x720, 25 fps",
+            "[info] Stream mapping:",
+            "[info]   Stream #0:1 -> #0:0 (h264 (native) -> rawvideo (native))",
+            "[info] Output #0, rawvideo, to 'pipe:':",
+            "[info]   Stream #0:0: Video: rawvideo (RGBA / 0x41424752), rgba, 640x360, 25 fps",
+        ];
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut pipe_log = PipeLog::new(tx, 0.0);
+        for line in log {
+            pipe_log.line(line);
+        }
+        assert_eq!(rx.try_recv(), Ok(((640, 360), (640, 360))));
+
+        // Without a mapping ffmpeg picks the largest video stream
+        let (tx, rx) = std::sync::mpsc::channel();
+        let mut pipe_log = PipeLog::new(tx, 0.0);
+        for line in log.iter().filter(|line| !line.contains("->")) {
+            pipe_log.line(line);
+        }
+        assert_eq!(rx.try_recv(), Ok(((1280, 720), (640, 360))));
+    }
+
+    #[test]
+    fn seeking_floors_to_milliseconds() {
+        let mut cmd = Command::new("ffmpeg");
+        assert_eq!(seek_range(&mut cmd, 1.9996, 4.3), 1.999);
+        let args: Vec<_> = cmd.get_args().collect();
+        assert_eq!(args, ["-ss", "1.999", "-t", "2.301"]);
+        assert_eq!(millis_arg(floor_millis(0.3)), "0.300");
+    }
+}
@@ -1,4 +1,5 @@
 use assert_cmd::prelude::*;
+use assert_fs::fixture::ChildPath;
 use assert_fs::prelude::*;
 use predicates::prelude::*;
 use std::process::Command;
@@ -38,6 +39,26 @@ fn have_system_ffmpeg() -> bool {
     which::which("ffmpeg").is_ok()
 }
 
+/// Helper: renders a 320x180 deck of `slides` 2s slides with the synth subcommand
+/// into a new temp dir, returning the dir and the video, or None without ffmpeg.
+fn synth_deck(slides: u32, extra_args: &[&str]) -> Option<(assert_fs::TempDir, ChildPath)> {
+    if !have_system_ffmpeg() {
+        eprintln!("Skipping: system ffmpeg missing (needed to render the synthetic deck).");
+        return None;
+    }
+
+    let td = assert_fs::TempDir::new().unwrap();
+    let input = td.child("deck.mp4");
+    Command::cargo_bin("slidesplit").unwrap()
+        .args(["synth", "--slides", &slides.to_string(), "--durations", "2"])
+        .args(["--width", "320", "--height", "180", "--fps", "10"])
+        .args(extra_args)
+        .arg(input.path())
+        .assert()
+        .success();
+    Some((td, input))
+}
+
 #[test]
 fn splits_exact_slides_without_transitions() {
     // Arrange: 3-frame slideshow @1s each, hard cuts
@@ -115,14 +136,12 @@ fn splits_through_crossfade() {
     // Build a 3-slide video with cross-fades between slides
     // We'll just do: slide0 1s -> fade 0.5s -> slide1 1s -> fade 0.5s -> slide2 1s
     // Implement via a filter_complex script:
-    let filter = format!(
-        "\
+    let filter = "\
         [0:v]format=rgba,trim=0:1,setpts=PTS-STARTPTS[a0]; \
         [1:v]format=rgba,trim=0:1,setpts=PTS-STARTPTS[a1]; \
         [2:v]format=rgba,trim=0:1,setpts=PTS-STARTPTS[a2]; \
         [a0][a1]xfade=transition=fade:duration=0.5:offset=0.5[b0]; \
-        [b0][a2]xfade=transition=fade:duration=0.5:offset=1.5[outv]"
-    );
+        [b0][a2]xfade=transition=fade:duration=0.5:offset=1.5[outv]";
 
     let status = Command::new("ffmpeg")
         .args([
@@ -130,7 +149,7 @@ fn splits_through_crossfade() {
             "-loop","1","-t","1","-i", frames[0].to_str().unwrap(),
             "-loop","1","-t","1","-i", frames[1].to_str().unwrap(),
             "-loop","1","-t","1","-i", frames[2].to_str().unwrap(),
-            "-filter_complex", &filter,
+            "-filter_complex", filter,
             "-map","[outv]",
             "-pix_fmt","yuv420p",
             input.path().to_str().unwrap(),
@@ -158,3 +177,131 @@ fn splits_through_crossfade() {
         .count();
     assert_eq!(entries, 3, "expected exactly 3 outputs despite the cross-fades");
 }
+
+#[test]
+fn synthetic_deck_round_trips_through_eval() {
+    let Some((td, input)) = synth_deck(4, &[]) else {
+        return;
+    };
+    let truth = td.child("deck_truth.csv");
+    truth.assert(predicate::str::contains("time\n2.000\n4.000\n6.000\n"));
+
+    Command::cargo_bin("slidesplit").unwrap()
+        .arg("eval")
+        .arg(input.path())
+        .arg("--truth").arg(truth.path())
+        .arg("--fps").arg("2.0")
+        .arg("--min-stable-seconds").arg("0.5")
+        .arg("-o").arg(td.child("out").path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("F1 1.000"));
+}
+
+#[test]
+fn tune_tabulates_slide_counts_of_synthetic_deck() {
+    let Some((td, input)) = synth_deck(3, &[]) else {
+        return;
+    };
+
+    let csv = td.child("tune.csv");
+    let out_dir = td.child("out");
+    Command::cargo_bin("slidesplit").unwrap()
+        .arg("tune")
+        .arg(input.path())
+        .arg("--fps").arg("2.0")
+        .arg("--thresholds").arg("10")
+        .arg("--min-stable").arg("0.5")
+        .arg("--csv").arg(csv.path())
+        .arg("-o").arg(out_dir.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Slides per --threshold"));
+    csv.assert("threshold,min_stable_seconds,slides\n10,0.5,3\n");
+    // Nothing is written to the output directory without --auto-mask
+    out_dir.assert(predicate::path::missing());
+}
+
+#[test]
+fn scene_detector_splits_synthetic_deck() {
+    let Some((td, input)) = synth_deck(3, &[]) else {
+        return;
+    };
+
+    let out_dir = td.child("out");
+    Command::cargo_bin("slidesplit").unwrap()
+        .arg(input.path())
+        .arg("--detector").arg("scene")
+        .arg("--min-stable-seconds").arg("0.5")
+        .arg("-o").arg(out_dir.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Wrote 3 slide"));
+}
+
+#[test]
+fn freeze_detector_splits_synthetic_deck() {
+    let Some((td, input)) = synth_deck(3, &["--transition", "fade"]) else {
+        return;
+    };
+
+    let out_dir = td.child("out");
+    Command::cargo_bin("slidesplit").unwrap()
+        .arg(input.path())
+        .arg("--detector").arg("freeze")
+        .arg("--min-stable-seconds").arg("0.5")
+        .arg("-o").arg(out_dir.path())
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Wrote 3 slide"));
+}
+
+#[test]
+fn pipe_mode_splits_synthetic_deck() {
+    let Some((td, input)) = synth_deck(3, &[]) else {
+        return;
+    };
+
+    // Full-resolution frames, then grayscale frames downscaled for hashing
+    let runs = [("out", vec!["--pipe"]), ("out_small", vec!["--pipe", "--hash-width", "64"])];
+    for (name, args) in runs {
+        let out_dir = td.child(name);
+        Command::cargo_bin("slidesplit").unwrap()
+            .arg(input.path())
+            .args(&args)
+            .arg("--min-stable-seconds").arg("0.5")
+            .arg("-o").arg(out_dir.path())
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Wrote 3 slide"));
+        let slide = image::open(out_dir.child("slide_02.png").path()).unwrap();
+        assert_eq!(slide.width(), 320, "slides are written at full resolution");
+    }
+}
+
+#[test]
+fn keyframe_scan_splits_synthetic_deck() {
+    // synth forces keyframes at the slide changes
+    let Some((td, input)) = synth_deck(3, &[]) else {
+        return;
+    };
+
+    let runs: [(&str, &[&str]); 3] = [
+        ("out", &[]),
+        ("out_refined", &["--refine", "--keep-temps"]),
+        ("out_refined_piped", &["--refine", "--hash-width", "64"]),
+    ];
+    for (name, extra) in runs {
+        Command::cargo_bin("slidesplit").unwrap()
+            .arg(input.path())
+            .arg("--keyframes-only")
+            .arg("--min-stable-seconds").arg("0.5")
+            .args(extra)
+            .arg("-o").arg(td.child(name).path())
+            .assert()
+            .success()
+            .stdout(predicate::str::contains("Wrote 3 slide"));
+    }
+    let refined = td.child("out_refined").child("frames_raw").child("refine_0001");
+    assert!(refined.path().is_dir(), "--keep-temps keeps the frames decoded by --refine");
+}
@@ -1,6 +1,14 @@
-use slidesplit::{cluster_frames, merge_short_clusters, FrameEntry};
+use slidesplit::{
+    cluster_frames, cluster_span, dedupe_clusters, detect_content_area, detect_dynamic_regions,
+    detect_screen_quad, diagnose_clusters, estimate_threshold, fit_slide_count, fuse_frames,
+    laplacian_variance, merge_short_clusters, parse_timestamp, parse_truth, score_boundaries,
+    segment_change_points, select_representatives, split_builds, AnchorStrategy, ClusterStrategy,
+    Coord, Corners, FrameEntry, FrameFusion, HashFusion, IncrementalClusterer, MultiHash, Rect,
+    RegionMask, RepresentativeRule, SlidingWindowStrategy,
+};
 use img_hash::ImageHash;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 fn h64(u: u64) -> ImageHash {
     // Construct a synthetic 64-bit hash from an integer (big-endian order)
@@ -8,6 +16,16 @@ fn h64(u: u64) -> ImageHash {
     ImageHash::from_bytes(&bytes).unwrap()
 }
 
+/// Synthetic frame sampled at 2 fps
+fn frame(idx: usize, prefix: &str, hash: u64) -> FrameEntry {
+    FrameEntry::new(idx, idx as f64 / 2.0, PathBuf::from(format!("{prefix}{idx}.png")), h64(hash))
+}
+
+/// Synthetic frames `f0.png`, `f1.png`, ... with the given hashes, sampled at 2 fps
+fn frames_from_hashes(hashes: &[u64]) -> Vec<FrameEntry> {
+    hashes.iter().enumerate().map(|(i, &h)| frame(i, "f", h)).collect()
+}
+
 
 #[test]
 fn clusters_split_when_distance_exceeds_threshold() {
@@ -15,18 +33,10 @@ fn clusters_split_when_distance_exceeds_threshold() {
     // 5..=9 similar to each other but far from 0..=4.
     let mut frames = Vec::new();
     for i in 0..5 {
-        frames.push(FrameEntry {
-            idx: i,
-            path: PathBuf::from(format!("f{i}.png")),
-            hash: h64(0xAAAA_AAAA_AAAA_AAAA ^ i as u64),
-        });
+        frames.push(frame(i, "f", 0xAAAA_AAAA_AAAA_AAAA ^ i as u64));
     }
     for i in 5..10 {
-        frames.push(FrameEntry {
-            idx: i,
-            path: PathBuf::from(format!("f{i}.png")),
-            hash: h64(0x5555_5555_5555_5555 ^ i as u64),
-        });
+        frames.push(frame(i, "f", 0x5555_5555_5555_5555 ^ i as u64));
     }
 
     // A modest threshold splits into two clusters
@@ -45,27 +55,15 @@ fn merge_short_clusters_stabilizes_crossfade_blips() {
     let mut frames = Vec::new();
     // A cluster
     for i in 0..8 {
-        frames.push(FrameEntry {
-            idx: i,
-            path: PathBuf::from(format!("a{i}.png")),
-            hash: h64(0x0000_0000_0000_0000 ^ i as u64),
-        });
+        frames.push(frame(i, "a", i as u64));
     }
     // Transition mini-cluster
     for i in 8..11 {
-        frames.push(FrameEntry {
-            idx: i,
-            path: PathBuf::from(format!("t{i}.png")),
-            hash: h64(0x0F0F_0F0F_0F0F_0F0F ^ i as u64),
-        });
+        frames.push(frame(i, "t", 0x0F0F_0F0F_0F0F_0F0F ^ i as u64));
     }
     // B cluster
     for i in 11..19 {
-        frames.push(FrameEntry {
-            idx: i,
-            path: PathBuf::from(format!("b{i}.png")),
-            hash: h64(0xFFFF_FFFF_FFFF_FFFF ^ i as u64),
-        });
+        frames.push(frame(i, "b", 0xFFFF_FFFF_FFFF_FFFF ^ i as u64));
     }
 
     // First pass: we likely get three clusters
@@ -73,7 +71,528 @@ fn merge_short_clusters_stabilizes_crossfade_blips() {
     assert_eq!(clusters.len(), 3);
 
     // Merge with min_stable_seconds so the 3-frame transition collapses
-    // Suppose fps=2.0 => min_len = ceil(1.0*2.0)=2 frames; use 1.5s to be stricter
-    merge_short_clusters(&mut clusters, &frames, 1.5, 2.0, 8);
+    // Frames are 0.5s apart, so the transition lasts 1.5s; require 2s
+    merge_short_clusters(&mut clusters, &frames, 2.0, 8);
     assert_eq!(clusters.len(), 2, "Transition cluster should be merged away");
 }
+
+#[test]
+fn sliding_window_follows_gradual_drift() {
+    // Each frame flips one more bit than the previous one: 12 frames drift
+    // 11 bits away from the first frame, but never more than 1 bit per step.
+    let mut frames = Vec::new();
+    let mut bits = 0u64;
+    for i in 0..12 {
+        frames.push(frame(i, "d", bits));
+        bits |= 1 << i;
+    }
+
+    // The anchor strategy splits once the drift exceeds the threshold...
+    assert!(cluster_frames(&frames, 4).len() > 1);
+
+    // ...while the rolling mean keeps up with it.
+    let clusters = SlidingWindowStrategy { window: 3 }.cluster(&frames, 4);
+    assert_eq!(clusters, vec![(0..12).collect::<Vec<_>>()]);
+}
+
+#[test]
+fn dedupe_groups_revisited_slides() {
+    // Slides A, B, A (revisited), C with a couple of noisy bits each
+    let slides = [
+        0x0000_0000_0000_0000u64,
+        0xFFFF_FFFF_0000_0000,
+        0x0000_0000_0000_0003,
+        0x0000_0000_FFFF_FFFF,
+    ];
+    let mut frames = Vec::new();
+    for (s, base) in slides.iter().enumerate() {
+        for j in 0..4 {
+            let i = s * 4 + j;
+            frames.push(frame(i, "f", base ^ ((j as u64) << 8)));
+        }
+    }
+
+    let clusters = cluster_frames(&frames, 8);
+    assert_eq!(clusters.len(), 4);
+
+    let groups = dedupe_clusters(&clusters, &frames, 8);
+    assert_eq!(groups, vec![vec![0, 2], vec![1], vec![3]]);
+}
+
+#[test]
+fn estimate_threshold_splits_noise_from_cuts() {
+    // Three low-contrast slides (20 bits apart) with up to 2 bits of noise
+    let bases = [0u64, 0x0000_0000_000F_FFFF, 0x0000_00FF_FFF0_0000];
+    let mut frames = Vec::new();
+    for (s, base) in bases.iter().enumerate() {
+        for j in 0..6u64 {
+            let i = s * 6 + j as usize;
+            frames.push(frame(i, "f", base ^ ((j & 3) << 40)));
+        }
+    }
+
+    let t = estimate_threshold(&frames).expect("distances vary");
+    assert!((2..20).contains(&t), "threshold {t} should separate noise from cuts");
+    assert_eq!(cluster_frames(&frames, t).len(), 3);
+
+    // Identical frames give nothing to split
+    let still: Vec<FrameEntry> = frames[..3]
+        .iter()
+        .map(|f| FrameEntry { hash: h64(0), ..f.clone() })
+        .collect();
+    assert_eq!(estimate_threshold(&still), None);
+}
+
+#[test]
+fn change_points_keep_slow_crossfade_as_one_boundary() {
+    // Slide A (all zeros) for 6 frames, an 8-frame cross-fade whose hash bits
+    // flip mostly around the midpoint, then slide B (32 bits set) for 6 frames.
+    let mut hashes = vec![0u64; 6];
+    for flipped in [2u32, 4, 8, 16, 24, 28, 30, 32] {
+        hashes.push(u64::MAX >> (64 - flipped));
+    }
+    hashes.extend([u64::MAX >> 32; 6]);
+    let frames = frames_from_hashes(&hashes);
+
+    // The greedy anchor loop chops the fade into several pieces
+    assert!(cluster_frames(&frames, 10).len() > 2);
+
+    // The change-point partition covers every frame exactly once, in order...
+    let clusters = segment_change_points(&frames, 20.0);
+    let flat: Vec<usize> = clusters.iter().flatten().copied().collect();
+    assert_eq!(flat, (0..frames.len()).collect::<Vec<_>>());
+    // ...and finds a single boundary inside the fade
+    assert_eq!(clusters.len(), 2);
+    assert!((6..14).contains(&clusters[1][0]));
+}
+
+#[test]
+fn incremental_clusterer_matches_batch() {
+    // Pseudo-random deck: slides of 1..8 frames with small noise, separated by
+    // occasional transition frames half-way between two slides, sampled at a
+    // variable frame rate.
+    let mut state = 0x9E37_79B9_7F4A_7C15u64;
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    let mut hashes = Vec::new();
+    let mut slide = next();
+    for _ in 0..40 {
+        for _ in 0..(next() % 8 + 1) {
+            hashes.push(slide ^ (1 << (next() % 64)));
+        }
+        let following = next();
+        if next() % 2 == 0 {
+            hashes.push((slide & 0xFFFF_FFFF_0000_0000) | (following & 0x0000_0000_FFFF_FFFF));
+        }
+        slide = following;
+    }
+    let mut pts = 0.0;
+    let frames: Vec<FrameEntry> = hashes
+        .iter()
+        .enumerate()
+        .map(|(i, &h)| {
+            pts += 0.25 * (next() % 4 + 1) as f64;
+            FrameEntry { pts, ..frame(i, "f", h) }
+        })
+        .collect();
+
+    for (threshold, min_stable_seconds) in [(8, 1.0), (10, 2.0), (12, 0.0), (20, 1.5)] {
+        let mut batch = cluster_frames(&frames, threshold);
+        merge_short_clusters(&mut batch, &frames, min_stable_seconds, threshold);
+
+        let mut inc = IncrementalClusterer::new(threshold, min_stable_seconds);
+        let mut streamed = Vec::new();
+        for frame in &frames {
+            streamed.extend(inc.push(frame.clone()));
+        }
+        let before_finish = streamed.len();
+        assert!(inc.held() < frames.len(), "frames of emitted clusters should be dropped");
+        streamed.extend(inc.finish());
+
+        assert_eq!(streamed, batch, "threshold={threshold}, min_stable={min_stable_seconds}");
+        assert!(before_finish > 0, "clusters should be emitted before the stream ends");
+    }
+}
+
+#[test]
+fn incremental_clusterer_caps_lookahead_on_streams_that_never_settle() {
+    // Every frame differs from the previous one, so no cluster ever lasts 2s
+    let mut inc = IncrementalClusterer::new(10, 2.0).with_max_lookahead(8);
+    let mut streamed = Vec::new();
+    for i in 0..100 {
+        streamed.extend(inc.push(frame(i, "f", if i % 2 == 0 { 0 } else { !0 })));
+        assert!(inc.held() <= 8, "held {} frames after frame {i}", inc.held());
+    }
+    assert!(streamed.len() > 1, "clusters should be forced out before the stream ends");
+    streamed.extend(inc.finish());
+
+    let order: Vec<usize> = streamed.concat();
+    assert_eq!(order, (0..100).collect::<Vec<_>>());
+}
+
+#[test]
+fn split_builds_chains_bullet_reveals() {
+    use image::{GrayImage, Luma};
+
+    // White 40x30 slides; each bullet is a dark bar on its own line
+    let slide = |bullets: &[u32]| {
+        let mut img = GrayImage::from_pixel(40, 30, Luma([255]));
+        for &line in bullets {
+            for x in 4..30 {
+                for y in line * 6 + 2..line * 6 + 5 {
+                    img.put_pixel(x, y, Luma([0]));
+                }
+            }
+        }
+        img
+    };
+    // Frames: title (0-1), +bullet (2-3), +bullet (4-5), unrelated slide (6-7)
+    let thumbs = vec![
+        slide(&[0]),
+        slide(&[0]),
+        slide(&[0, 1]),
+        slide(&[0, 1]),
+        slide(&[0, 1, 2]),
+        slide(&[0, 1, 2]),
+        slide(&[3]),
+        slide(&[3]),
+    ];
+    // The clusterer merged the second bullet into the first build step
+    let clusters = vec![vec![0, 1], vec![2, 3, 4, 5], vec![6, 7]];
+
+    let builds = split_builds(&clusters, &thumbs);
+    assert_eq!(
+        builds,
+        vec![
+            vec![vec![0, 1], vec![2, 3], vec![4, 5]],
+            vec![vec![6, 7]],
+        ]
+    );
+}
+
+#[test]
+fn diagnostics_flag_merged_clusters() {
+    // Slide A, a 1-frame blip, slide A', slide B far away
+    let hashes = [0u64, 0, 0, 0xFFFF, 0xFFF0_0000, 0xFFF0_0000, 0xFFF0_0000, !0, !0, !0];
+    let frames = frames_from_hashes(&hashes);
+
+    let initial = cluster_frames(&frames, 10);
+    assert_eq!(initial.len(), 4);
+    let mut clusters = initial.clone();
+    merge_short_clusters(&mut clusters, &frames, 1.0, 10);
+    assert_eq!(clusters.len(), 3);
+
+    let diags = diagnose_clusters(&initial, &clusters, &frames, 10);
+    assert_eq!(diags.len(), 3);
+    // The blip was merged into A and inflates its spread
+    assert_eq!(diags[0].merged_from, 2);
+    assert_eq!(diags[0].boundary_next, Some(28));
+    assert!(diags[0].spread > diags[1].spread);
+    // B survived untouched behind a strong boundary
+    assert_eq!(diags[2].merged_from, 1);
+    assert_eq!(diags[2].boundary_prev, Some(52));
+    assert_eq!(diags[2].boundary_next, None);
+    assert!(diags[2].confidence > 0.9);
+    assert!(diags[0].confidence < 0.5);
+}
+
+#[test]
+fn fused_distance_combines_hash_types() {
+    let with_extra = |primary: u64, extra: [u64; 2], fusion: HashFusion| FrameEntry {
+        multi: Some(MultiHash {
+            extra: extra.iter().map(|&h| h64(h)).collect(),
+            weights: Arc::from([2.0, 1.0, 1.0]),
+            fusion,
+        }),
+        ..frame(0, "f", primary)
+    };
+
+    // Primary hash sees 8 bits of change, the extra hashes 0 and 40
+    for (fusion, expected) in [(HashFusion::Weighted, 14), (HashFusion::Vote, 8)] {
+        let a = with_extra(0, [0, 0], fusion);
+        let b = with_extra(0xFF, [0, u64::MAX >> 24], fusion);
+        assert_eq!(a.dist(&b), expected, "{fusion:?}");
+    }
+
+    // Frames without extra hashes fall back to the primary distance
+    assert_eq!(frame(0, "f", 0).dist(&frame(1, "f", 0xFF)), 8);
+}
+
+#[test]
+fn merge_short_clusters_uses_timestamps() {
+    // Slide A, then slide B of only two sampled frames that nonetheless stayed
+    // on screen for 3 seconds (frames in between failed to decode), then slide C.
+    let deck = |b_times: [f64; 2], c_start: f64| {
+        let mut frames: Vec<FrameEntry> = (0..4).map(|i| frame(i, "a", 0)).collect();
+        for (j, &pts) in b_times.iter().enumerate() {
+            frames.push(FrameEntry { pts, ..frame(4 + j, "b", 0xFFFF_FFFF) });
+        }
+        for i in 6..10 {
+            let pts = c_start + (i - 6) as f64 / 2.0;
+            frames.push(FrameEntry { pts, ..frame(i, "c", !0) });
+        }
+        frames
+    };
+
+    let frames = deck([2.0, 4.5], 5.0);
+    let mut clusters = cluster_frames(&frames, 8);
+    assert_eq!(clusters.len(), 3);
+    merge_short_clusters(&mut clusters, &frames, 2.0, 8);
+    assert_eq!(clusters.len(), 3, "B lasted 3s and must survive a 2s minimum");
+
+    // The same two frames shown for only one second are merged away
+    let frames = deck([2.0, 2.5], 3.0);
+    let mut clusters = cluster_frames(&frames, 8);
+    merge_short_clusters(&mut clusters, &frames, 2.0, 8);
+    assert_eq!(clusters.len(), 2);
+
+    // A known end time excludes the gap before C (e.g. a transition between freezes)
+    let mut frames = deck([2.0, 2.5], 5.0);
+    frames[5].end = Some(3.0);
+    assert_eq!(cluster_span(&[4, 5], &frames), (2.0, 3.0));
+    let mut clusters = cluster_frames(&frames, 8);
+    merge_short_clusters(&mut clusters, &frames, 2.0, 8);
+    assert_eq!(clusters.len(), 2);
+}
+
+#[test]
+fn representatives_follow_the_selected_rule() {
+    // A slide with a cursor blip at frame 1, fading into the next slide at frames 6-7
+    let hashes = [0, 0b111, 0, 0, 0, 0, 0xFF, 0xFFFF];
+    let frames = frames_from_hashes(&hashes);
+    let cluster: Vec<usize> = (0..frames.len()).collect();
+    let pick = |rule, count, sharpness: &[f64]| {
+        select_representatives(&cluster, &frames, rule, count, 10, sharpness)
+    };
+
+    assert_eq!(pick(RepresentativeRule::Median, 1, &[]), vec![4]);
+    assert_eq!(pick(RepresentativeRule::Median, 2, &[]), vec![2, 6]);
+    assert_eq!(pick(RepresentativeRule::LastStable, 1, &[]), vec![5]);
+    assert_eq!(pick(RepresentativeRule::Centroid, 1, &[]), vec![4]);
+    assert_eq!(pick(RepresentativeRule::Centroid, 3, &[]), vec![3, 4, 5]);
+
+    let sharpness = [1.0, 2.0, 9.0, 3.0, 3.0, 8.0, 0.5, 0.1];
+    assert_eq!(pick(RepresentativeRule::Sharpest, 2, &sharpness), vec![2, 5]);
+    // Without scores the sharpest rule degrades to the median
+    assert_eq!(pick(RepresentativeRule::Sharpest, 1, &[]), vec![4]);
+    // Never more representatives than frames
+    assert_eq!(pick(RepresentativeRule::Median, 20, &[]).len(), 8);
+
+    use image::{GrayImage, Luma};
+    let flat = GrayImage::from_pixel(8, 8, Luma([128]));
+    let edges = GrayImage::from_fn(8, 8, |x, _| Luma([if x < 4 { 0 } else { 255 }]));
+    assert_eq!(laplacian_variance(&flat), 0.0);
+    assert!(laplacian_variance(&edges) > 0.0);
+}
+
+#[test]
+fn fused_frames_drop_transient_overlays() {
+    use image::{Rgb, RgbImage};
+    // A gray slide with a white cursor wandering over it, plus one noisy frame
+    let mut images: Vec<RgbImage> = (0..5)
+        .map(|i| {
+            let mut img = RgbImage::from_pixel(5, 4, Rgb([100, 100, 100]));
+            img.put_pixel(i, 1, Rgb([255, 255, 255]));
+            img
+        })
+        .collect();
+    images[4].put_pixel(3, 3, Rgb([0, 0, 0]));
+
+    let median = fuse_frames(&images, FrameFusion::Median).unwrap();
+    assert!(median.pixels().all(|p| *p == Rgb([100, 100, 100])));
+    let trimmed = fuse_frames(&images, FrameFusion::TrimmedMean).unwrap();
+    assert!(trimmed.pixels().all(|p| *p == Rgb([100, 100, 100])));
+
+    images.push(RgbImage::new(2, 2));
+    assert!(fuse_frames(&images, FrameFusion::Median).is_none());
+    assert!(fuse_frames(&[], FrameFusion::Median).is_none());
+}
+
+#[test]
+fn region_masks_blank_ignored_areas() {
+    use image::{Rgba, RgbaImage};
+    let webcam: Rect = "0.75,0.75,0.25,0.25".parse().unwrap();
+    assert_eq!(webcam.x, Coord::Fraction(0.75));
+    let title: Rect = "0,0,8,2".parse().unwrap();
+    assert_eq!(title.w, Coord::Pixels(8));
+    assert_eq!(title.to_pixels(6, 6), (0, 0, 6, 2));
+    assert!("1,2,3".parse::<Rect>().is_err());
+    assert_eq!(webcam.to_string(), "0.75,0.75,0.25,0.25");
+    assert_eq!("0,0,1.0,1".parse::<Rect>().unwrap().to_string(), "0,0,1.0,1");
+    assert_eq!("0.00001,0,0.5,1".parse::<Rect>().unwrap().to_string(), "0.00001,0,0.5,1");
+    assert!("0,0,1.5,1".parse::<Rect>().is_err());
+
+    let white = Rgba([255, 255, 255, 255]);
+    let black = Rgba([0, 0, 0, 255]);
+    let mut img = RgbaImage::from_pixel(8, 8, white);
+    RegionMask { include: vec![], exclude: vec![webcam] }.apply(&mut img);
+    assert_eq!(*img.get_pixel(6, 6), black);
+    assert_eq!(*img.get_pixel(5, 6), white);
+
+    let mask = RegionMask { include: vec![title], exclude: vec![] };
+    let mut img = RgbaImage::from_pixel(8, 8, white);
+    mask.apply(&mut img);
+    assert_eq!(*img.get_pixel(7, 1), white);
+    assert_eq!(*img.get_pixel(0, 2), black);
+    assert_eq!(mask.include_bounds(8, 8), Some((0, 0, 8, 2)));
+    assert_eq!(RegionMask::default().include_bounds(8, 8), None);
+
+    // Pixel regions follow a resized frame, fractions stay put
+    let half = RegionMask { include: vec![title], exclude: vec![webcam] }.scaled(0.5, 0.5);
+    assert_eq!(half.include[0].to_pixels(4, 4), (0, 0, 4, 1));
+    assert_eq!(half.exclude[0], webcam);
+}
+
+#[test]
+fn dynamic_regions_are_found_around_a_webcam_tile() {
+    use image::{GrayImage, Luma};
+    // 64x32 thumbnails: two slides, with a flickering 16x16 webcam tile in the corner
+    let thumbs: Vec<GrayImage> = (0..12u32)
+        .map(|i| {
+            let slide = if i < 6 { 40 } else { 200 };
+            GrayImage::from_fn(64, 32, |x, y| {
+                if x >= 48 && y >= 16 {
+                    Luma([((i * 37 + x * 5) % 256) as u8])
+                } else {
+                    Luma([slide])
+                }
+            })
+        })
+        .collect();
+
+    let regions = detect_dynamic_regions(&thumbs);
+    assert_eq!(regions.len(), 1);
+    assert_eq!(regions[0].to_pixels(640, 320), (480, 160, 160, 160));
+
+    // A fully static video has nothing to mask
+    assert!(detect_dynamic_regions(&thumbs[..1]).is_empty());
+    let still = vec![GrayImage::from_pixel(64, 32, Luma([90])); 5];
+    assert!(detect_dynamic_regions(&still).is_empty());
+}
+
+#[test]
+fn content_area_excludes_letterbox_bars() {
+    use image::{GrayImage, Luma};
+    // 16:9 thumbnails with a 4:3 deck in the middle (pillarbox) and faint bar noise
+    let thumbs: Vec<GrayImage> = (0..4u32)
+        .map(|i| {
+            GrayImage::from_fn(64, 36, |x, y| {
+                if (8..56).contains(&x) {
+                    Luma([if (x + y + i) % 7 == 0 { 20 } else { 230 }])
+                } else {
+                    Luma([((x * 3 + y + i) % 10) as u8])
+                }
+            })
+        })
+        .collect();
+
+    let area = detect_content_area(&thumbs).unwrap();
+    assert_eq!(area.to_pixels(640, 360), (80, 0, 480, 360));
+
+    let full = vec![GrayImage::from_pixel(64, 36, Luma([128])); 2];
+    assert!(detect_content_area(&full).is_none());
+    let dark = vec![GrayImage::new(64, 36); 2];
+    assert!(detect_content_area(&dark).is_none());
+}
+
+#[test]
+fn projector_screen_is_detected_and_rectified() {
+    use image::{GrayImage, Luma};
+    // Camera view of a screen: a bright trapezoid, wider at the bottom, on a dark wall.
+    // The left half of the slide is darker so the rectified orientation can be checked.
+    let inside = |x: f32, y: f32| {
+        let t = (y - 10.0) / 40.0;
+        let (left, right) = (20.0 - 8.0 * t, 80.0 + 8.0 * t);
+        (0.0..=1.0).contains(&t) && x >= left && x <= right
+    };
+    let thumb = GrayImage::from_fn(100, 60, |x, y| {
+        let (fx, fy) = (x as f32 + 0.5, y as f32 + 0.5);
+        match (inside(fx, fy), fx < 50.0) {
+            (true, true) => Luma([170]),
+            (true, false) => Luma([240]),
+            (false, _) => Luma([15]),
+        }
+    });
+
+    let quad = detect_screen_quad(std::slice::from_ref(&thumb)).unwrap();
+    let expected = [(20.0, 10.0), (80.0, 10.0), (88.0, 50.0), (12.0, 50.0)];
+    for (found, want) in quad.corners.iter().zip(expected) {
+        assert!((found.0 - want.0).abs() <= 1.5 && (found.1 - want.1).abs() <= 1.5, "{found:?}");
+    }
+
+    let flat = quad.rectify(&thumb).unwrap();
+    let (w, h) = flat.dimensions();
+    assert_eq!((w, h), quad.size());
+    for y in [2, h / 2, h - 3] {
+        assert!((160..=180).contains(&flat.get_pixel(3, y)[0]));
+        assert!(flat.get_pixel(w - 4, y)[0] >= 230);
+    }
+
+    let corners: Corners = "0,0,0.5,0,0.5,0.5,0,0.5".parse().unwrap();
+    assert_eq!(corners.to_quad(100, 60).size(), (50, 30));
+    assert!("1,2,3,4".parse::<Corners>().is_err());
+
+    // A front-on screen capture has no screen to detect
+    let capture = GrayImage::from_pixel(100, 60, Luma([230]));
+    assert!(detect_screen_quad(&[capture]).is_none());
+}
+
+#[test]
+fn slide_count_fit_finds_parameters_for_a_known_deck() {
+    // Three slides with a noisy middle one (frames up to 6 bits apart)
+    let hashes = [0u64, 0, 0, 0, 0xFF00, 0xFF3F, 0xFF00, 0xFF3F, !0, !0, !0, !0];
+    let frames = frames_from_hashes(&hashes);
+    let strategy = AnchorStrategy;
+
+    let fit = fit_slide_count(&frames, &strategy, 3, &[1.0, 2.0]).unwrap();
+    assert_eq!(fit.slides, 3);
+    assert!((6..16).contains(&fit.threshold), "threshold {}", fit.threshold);
+    assert_eq!(fit.min_stable_seconds, 1.0);
+
+    // Ten slides are out of reach: the closest count is reported instead
+    let fit = fit_slide_count(&frames, &strategy, 10, &[1.0]).unwrap();
+    assert!(fit.slides < 10);
+    assert!(fit_slide_count(&[], &strategy, 3, &[1.0]).is_none());
+}
+
+#[test]
+fn boundary_scores_match_within_tolerance() {
+    let truth = [10.0, 20.0, 30.0, 40.0];
+    // 12.5 is too far from 10; 19.5 and 20.4 compete for 20, the closer one wins
+    let predicted = [12.5, 19.5, 20.4, 30.9, 40.0];
+    let score = score_boundaries(&predicted, &truth, 1.0);
+    assert_eq!(score.matched, 3);
+    assert_eq!(score.count_error, 1);
+    assert!((score.precision - 0.6).abs() < 1e-9);
+    assert!((score.recall - 0.75).abs() < 1e-9);
+    assert!((score.f1 - 2.0 * 0.6 * 0.75 / 1.35).abs() < 1e-9);
+
+    let perfect = score_boundaries(&truth, &truth, 0.0);
+    assert_eq!((perfect.precision, perfect.recall, perfect.f1), (1.0, 1.0, 1.0));
+    let none = score_boundaries(&[], &truth, 1.0);
+    assert_eq!((none.recall, none.f1, none.count_error), (0.0, 0.0, -4));
+}
+
+#[test]
+fn truth_files_accept_headers_comments_and_clock_times() {
+    assert_eq!(parse_timestamp("12.5"), Some(12.5));
+    assert_eq!(parse_timestamp("01:02.5"), Some(62.5));
+    assert_eq!(parse_timestamp("1:01:02.25"), Some(3662.25));
+    for bad in ["", "1:2:3:4", "-1", "1:-2", "ten", "1::2", "inf", "NaN"] {
+        assert_eq!(parse_timestamp(bad), None, "{bad:?}");
+    }
+
+    let text = "time,title\n# opening slide\n0,Title\n\n00:01:30.50,Agenda\n 45 ,Intro\n";
+    assert_eq!(parse_truth(text), Ok(vec![45.0, 90.5]));
+    assert_eq!(parse_truth(""), Ok(vec![]));
+
+    // Only the first line may be a header
+    let err = parse_truth("time\n10\nlater\n").unwrap_err();
+    assert!(err.contains("line 3"), "{err}");
+    assert!(parse_truth("1:2:3:4\n").is_ok(), "an invalid first line is taken for a header");
+    assert!(parse_truth("10\n1:2:3:4\n").is_err());
+}